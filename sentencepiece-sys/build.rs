@@ -28,9 +28,14 @@ fn build_sentencepiece(builder: &mut Build) {
         "cargo:rustc-link-search=native={}",
         dst.join("lib").display()
     );
+    // The trainer library depends on the processor library.
+    println!("cargo:rustc-link-lib=static=sentencepiece_train");
     println!("cargo:rustc-link-lib=static=sentencepiece");
 
     builder.include("source/src");
+    // The shim may use sentencepiece internals that are not exported by
+    // an installed library.
+    builder.define("SPP_VENDORED", None);
 }
 
 fn find_sentencepiece(builder: &mut Build) -> bool {
@@ -66,6 +66,16 @@ pub struct SentencePieceProcessor {
 pub struct SentencePieceText {
     _unused: [u8; 0],
 }
+pub type spp_log_callback = ::std::option::Option<
+    unsafe extern "C" fn(line: *const ::std::os::raw::c_uchar, line_len: usize),
+>;
+pub type spt_sentence_callback = ::std::option::Option<
+    unsafe extern "C" fn(
+        state: *mut ::std::os::raw::c_void,
+        sentence: *mut *const ::std::os::raw::c_uchar,
+        sentence_len: *mut usize,
+    ) -> bool,
+>;
 extern "C" {
     pub fn spp_decode_piece_ids(
         spp: *mut SentencePieceProcessor,
@@ -84,6 +94,24 @@ extern "C" {
         decoded_len: *mut usize,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn spp_decode_piece_ids_as_serialized_proto(
+        spp: *mut SentencePieceProcessor,
+        pieces: *const u32,
+        pieces_len: usize,
+        proto: *mut *mut ::std::os::raw::c_uchar,
+        proto_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spp_decode_pieces_as_serialized_proto(
+        spp: *mut SentencePieceProcessor,
+        pieces: *const *const ::std::os::raw::c_char,
+        pieces_len: usize,
+        proto: *mut *mut ::std::os::raw::c_uchar,
+        proto_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn spp_encode_as_serialized_proto(
         spp: *mut SentencePieceProcessor,
@@ -93,18 +121,82 @@ extern "C" {
     ) -> *mut ::std::os::raw::c_uchar;
 }
 extern "C" {
-    pub fn spp_sample_encode_as_serialized_proto(
+    pub fn spp_encode_as_ids(
+        spp: *mut SentencePieceProcessor,
+        sentence: *const ::std::os::raw::c_char,
+        sentence_len: usize,
+        ids: *mut *mut u32,
+        ids_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spp_encode_as_pieces(
+        spp: *mut SentencePieceProcessor,
+        sentence: *const ::std::os::raw::c_char,
+        sentence_len: usize,
+        pieces: *mut *mut ::std::os::raw::c_uchar,
+        pieces_len: *mut usize,
+        piece_lens: *mut *mut usize,
+        piece_lens_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spp_nbest_encode_as_serialized_proto(
         spp: *mut SentencePieceProcessor,
         sentence: *const ::std::os::raw::c_char,
         sentence_len: usize,
         len: *mut usize,
         nbest: usize,
+    ) -> *mut ::std::os::raw::c_uchar;
+}
+extern "C" {
+    pub fn spp_sample_encode_as_serialized_proto(
+        spp: *mut SentencePieceProcessor,
+        sentence: *const ::std::os::raw::c_char,
+        sentence_len: usize,
+        len: *mut usize,
+        nbest: ::std::os::raw::c_int,
         alpha: f32,
     ) -> *mut ::std::os::raw::c_uchar;
 }
+extern "C" {
+    pub fn spp_sample_encode_and_score_as_serialized_proto(
+        spp: *mut SentencePieceProcessor,
+        sentence: *const ::std::os::raw::c_char,
+        sentence_len: usize,
+        len: *mut usize,
+        num_samples: ::std::os::raw::c_int,
+        alpha: f32,
+        wor: bool,
+        include_best: bool,
+    ) -> *mut ::std::os::raw::c_uchar;
+}
+extern "C" {
+    pub fn spp_normalize(
+        spp: *mut SentencePieceProcessor,
+        sentence: *const ::std::os::raw::c_char,
+        sentence_len: usize,
+        normalized: *mut *mut ::std::os::raw::c_uchar,
+        normalized_len: *mut usize,
+        norm_to_orig: *mut *mut usize,
+        norm_to_orig_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn spp_new() -> *mut SentencePieceProcessor;
 }
+extern "C" {
+    pub fn spp_set_decode_extra_options(
+        spp: *mut SentencePieceProcessor,
+        extra_options: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spp_set_encode_extra_options(
+        spp: *mut SentencePieceProcessor,
+        extra_options: *const ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn spp_from_serialized_proto(
         spp: *mut SentencePieceProcessor,
@@ -124,6 +216,23 @@ extern "C" {
         filename: *const ::std::os::raw::c_char,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn spp_load_vocabulary(
+        spp: *mut SentencePieceProcessor,
+        filename: *const ::std::os::raw::c_char,
+        threshold: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spp_reset_vocabulary(spp: *mut SentencePieceProcessor) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spp_set_vocabulary(
+        spp: *mut SentencePieceProcessor,
+        pieces: *const *const ::std::os::raw::c_char,
+        pieces_len: usize,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn spp_free(spp: *mut SentencePieceProcessor);
 }
@@ -133,9 +242,28 @@ extern "C" {
 extern "C" {
     pub fn spp_eos_id(spp: *mut SentencePieceProcessor) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn spp_get_score(spp: *mut SentencePieceProcessor, id: ::std::os::raw::c_int) -> f32;
+}
+extern "C" {
+    pub fn spp_id_to_piece(
+        spp: *mut SentencePieceProcessor,
+        id: ::std::os::raw::c_int,
+        len: *mut usize,
+    ) -> *mut ::std::os::raw::c_uchar;
+}
+extern "C" {
+    pub fn spp_is_byte(spp: *mut SentencePieceProcessor, id: ::std::os::raw::c_int) -> bool;
+}
+extern "C" {
+    pub fn spp_is_control(spp: *mut SentencePieceProcessor, id: ::std::os::raw::c_int) -> bool;
+}
 extern "C" {
     pub fn spp_is_unknown(spp: *mut SentencePieceProcessor, id: ::std::os::raw::c_int) -> bool;
 }
+extern "C" {
+    pub fn spp_is_unused(spp: *mut SentencePieceProcessor, id: ::std::os::raw::c_int) -> bool;
+}
 extern "C" {
     pub fn spp_pad_id(spp: *mut SentencePieceProcessor) -> ::std::os::raw::c_int;
 }
@@ -151,3 +279,42 @@ extern "C" {
 extern "C" {
     pub fn spp_unk_id(spp: *mut SentencePieceProcessor) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn spp_set_random_generator_seed(seed: ::std::os::raw::c_uint);
+}
+#[cfg(all(feature = "static", not(feature = "system")))]
+extern "C" {
+    pub fn spp_seed_random_generator(seed: ::std::os::raw::c_uint) -> *mut ::std::os::raw::c_void;
+}
+#[cfg(all(feature = "static", not(feature = "system")))]
+extern "C" {
+    pub fn spp_restore_random_generator(saved: *mut ::std::os::raw::c_void);
+}
+extern "C" {
+    pub fn spp_set_log_callback(callback: spp_log_callback);
+}
+extern "C" {
+    pub fn spp_set_min_log_level(level: ::std::os::raw::c_int);
+}
+extern "C" {
+    pub fn spt_train(
+        keys: *const *const ::std::os::raw::c_char,
+        values: *const *const ::std::os::raw::c_char,
+        len: usize,
+        message: *mut *mut ::std::os::raw::c_uchar,
+        message_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn spt_train_from_iterator(
+        keys: *const *const ::std::os::raw::c_char,
+        values: *const *const ::std::os::raw::c_char,
+        len: usize,
+        next_sentence: spt_sentence_callback,
+        state: *mut ::std::os::raw::c_void,
+        model: *mut *mut ::std::os::raw::c_uchar,
+        model_len: *mut usize,
+        message: *mut *mut ::std::os::raw::c_uchar,
+        message_len: *mut usize,
+    ) -> ::std::os::raw::c_int;
+}
@@ -1,14 +1,33 @@
+#include <atomic>
 #include <cstdint>
 #include <cstdlib>
 #include <cstring>
+#include <iostream>
+#include <random>
+#include <streambuf>
+#include <string>
 #include <type_traits>
+#include <unordered_map>
 #include <vector>
 
 #include <sentencepiece_processor.h>
+#include <sentencepiece_trainer.h>
 
 using absl::string_view;
+using sentencepiece::ImmutableSentencePieceText;
 using sentencepiece::SentencePieceProcessor;
 using sentencepiece::SentencePieceText;
+using sentencepiece::SentencePieceTrainer;
+
+#ifdef SPP_VENDORED
+namespace sentencepiece {
+namespace random {
+// Random generator of the calling thread that sentencepiece samples from.
+// Declared in util.h, which is not part of the installed headers.
+std::mt19937 *GetRandomGenerator();
+}  // namespace random
+}  // namespace sentencepiece
+#endif
 
 // Inspired by:
 // https://stackoverflow.com/a/14589519
@@ -18,6 +37,129 @@ constexpr auto to_underlying_type(E e) -> typename std::underlying_type<E>::type
    return static_cast<typename std::underlying_type<E>::type>(e);
 }
 
+typedef void (*spp_log_callback)(unsigned char const *line, size_t line_len);
+
+// Stream buffer that passes every line to a callback. Without a callback,
+// output is forwarded to the original stream buffer.
+class CallbackStreamBuf : public std::streambuf {
+public:
+  explicit CallbackStreamBuf(std::streambuf *original) : original_(original) {}
+
+  void set_callback(spp_log_callback callback) {
+    callback_.store(callback);
+  }
+
+protected:
+  int_type overflow(int_type ch) override {
+    if (!traits_type::eq_int_type(ch, traits_type::eof())) {
+      char c = traits_type::to_char_type(ch);
+      append(&c, 1);
+    }
+    return traits_type::not_eof(ch);
+  }
+
+  std::streamsize xsputn(char const *s, std::streamsize n) override {
+    append(s, static_cast<size_t>(n));
+    return n;
+  }
+
+  int sync() override {
+    return original_->pubsync();
+  }
+
+private:
+  void append(char const *s, size_t n) {
+    // Buffer per thread, so that lines of training threads are not mixed.
+    static thread_local std::string line;
+    line.append(s, n);
+
+    size_t start = 0;
+    for (size_t end; (end = line.find('\n', start)) != std::string::npos; start = end + 1) {
+      spp_log_callback callback = callback_.load();
+      if (callback == nullptr) {
+        original_->sputn(line.data() + start, end + 1 - start);
+      } else {
+        callback(reinterpret_cast<unsigned char const *>(line.data() + start), end - start);
+      }
+    }
+    line.erase(0, start);
+
+    // Incomplete lines are only buffered for the callback.
+    if (callback_.load() == nullptr && !line.empty()) {
+      original_->sputn(line.data(), line.size());
+      line.clear();
+    }
+  }
+
+  std::streambuf *original_;
+  std::atomic<spp_log_callback> callback_{nullptr};
+};
+
+// sentencepiece logs to std::cerr, so log lines are captured by replacing
+// its stream buffer. The stream buffer is installed once during start-up,
+// since std::cerr must not be modified while other threads write to it.
+static CallbackStreamBuf *install_log_buf() {
+  auto *buf = new CallbackStreamBuf(std::cerr.rdbuf());
+  std::cerr.rdbuf(buf);
+  return buf;
+}
+
+// Never destructed, std::cerr may still use it during program exit.
+static CallbackStreamBuf *log_buf = install_log_buf();
+
+typedef bool (*spt_sentence_callback)(void *state, unsigned char const **sentence, size_t *sentence_len);
+
+// Sentence iterator that retrieves sentences through a callback.
+class CallbackSentenceIterator : public sentencepiece::SentenceIterator {
+public:
+  CallbackSentenceIterator(spt_sentence_callback next_sentence, void *state) :
+    next_sentence_(next_sentence), state_(state), done_(false) {
+    // The trainer reads the current value before advancing.
+    Next();
+  }
+
+  bool done() const override {
+    return done_;
+  }
+
+  void Next() override {
+    unsigned char const *sentence = nullptr;
+    size_t sentence_len = 0;
+    done_ = !next_sentence_(state_, &sentence, &sentence_len);
+    if (!done_) {
+      value_.assign(reinterpret_cast<char const *>(sentence), sentence_len);
+    }
+  }
+
+  std::string const &value() const override {
+    return value_;
+  }
+
+  sentencepiece::util::Status status() const override {
+    return sentencepiece::util::OkStatus();
+  }
+
+private:
+  spt_sentence_callback next_sentence_;
+  void *state_;
+  bool done_;
+  std::string value_;
+};
+
+static std::unordered_map<std::string, std::string> to_kwargs(char const * const *keys, char const * const *values, size_t len) {
+  std::unordered_map<std::string, std::string> kwargs;
+  for (size_t i = 0; i < len; ++i) {
+    kwargs[keys[i]] = values[i];
+  }
+  return kwargs;
+}
+
+static void copy_string(std::string const &str, unsigned char **data, size_t *len) {
+  *len = str.size();
+  *data = static_cast<unsigned char *>(malloc(str.size()));
+  memcpy(*data, str.data(), str.size());
+}
+
 extern "C" {
 
 SentencePieceProcessor *spp_new() {
@@ -64,6 +206,44 @@ int spp_decode_pieces(SentencePieceProcessor *spp, char const * const *pieces, s
     return to_underlying_type(status.code());
 }
 
+int spp_decode_piece_ids_as_serialized_proto(SentencePieceProcessor *spp, uint32_t const *pieces, size_t pieces_len, unsigned char **proto, size_t *proto_len) {
+    std::vector<int> int_pieces;
+    int_pieces.reserve(pieces_len);
+
+    for (uint32_t const *piece = pieces; piece != pieces + pieces_len; ++piece) {
+        int_pieces.push_back(static_cast<int>(*piece));
+    }
+
+    ImmutableSentencePieceText spt;
+    auto status = spp->Decode(int_pieces, &spt);
+    auto serialized = spt.SerializeAsString();
+
+    *proto_len = serialized.size();
+    *proto = static_cast<unsigned char *>(malloc(serialized.size()));
+    memcpy(*proto, serialized.data(), serialized.size());
+
+    return to_underlying_type(status.code());
+}
+
+int spp_decode_pieces_as_serialized_proto(SentencePieceProcessor *spp, char const * const *pieces, size_t pieces_len, unsigned char **proto, size_t *proto_len) {
+    std::vector<absl::string_view> str_pieces;
+    str_pieces.reserve(pieces_len);
+
+    for (char const * const *piece = pieces; piece != pieces + pieces_len; ++piece) {
+        str_pieces.push_back(*piece);
+    }
+
+    ImmutableSentencePieceText spt;
+    auto status = spp->Decode(str_pieces, &spt);
+    auto serialized = spt.SerializeAsString();
+
+    *proto_len = serialized.size();
+    *proto = static_cast<unsigned char *>(malloc(serialized.size()));
+    memcpy(*proto, serialized.data(), serialized.size());
+
+    return to_underlying_type(status.code());
+}
+
 unsigned char *spp_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len) {
   auto sentence_view = absl::string_view(sentence, sentence_len);
   auto serialized = spp->EncodeAsSerializedProto(sentence_view);
@@ -76,9 +256,49 @@ unsigned char *spp_encode_as_serialized_proto(SentencePieceProcessor *spp, char
 }
 
 
-unsigned char *spp_sample_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, size_t nbest, float alpha) {
+int spp_encode_as_ids(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, uint32_t **ids, size_t *ids_len) {
+  auto sentence_view = absl::string_view(sentence, sentence_len);
+  std::vector<int> int_ids;
+  auto status = spp->Encode(sentence_view, &int_ids);
+
+  *ids_len = int_ids.size();
+  *ids = static_cast<uint32_t *>(malloc(int_ids.size() * sizeof(uint32_t)));
+  for (size_t i = 0; i < int_ids.size(); ++i) {
+    (*ids)[i] = static_cast<uint32_t>(int_ids[i]);
+  }
+
+  return to_underlying_type(status.code());
+}
+
+// The pieces are returned as a single buffer with the concatenated pieces,
+// together with the length of every piece.
+int spp_encode_as_pieces(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, unsigned char **pieces, size_t *pieces_len, size_t **piece_lens, size_t *piece_lens_len) {
+  auto sentence_view = absl::string_view(sentence, sentence_len);
+  std::vector<std::string> str_pieces;
+  auto status = spp->Encode(sentence_view, &str_pieces);
+
+  size_t total_len = 0;
+  for (auto const &piece : str_pieces) {
+    total_len += piece.size();
+  }
+
+  *pieces_len = total_len;
+  *pieces = static_cast<unsigned char *>(malloc(total_len));
+  *piece_lens_len = str_pieces.size();
+  *piece_lens = static_cast<size_t *>(malloc(str_pieces.size() * sizeof(size_t)));
+
+  unsigned char *data = *pieces;
+  for (size_t i = 0; i < str_pieces.size(); ++i) {
+    memcpy(data, str_pieces[i].data(), str_pieces[i].size());
+    data += str_pieces[i].size();
+    (*piece_lens)[i] = str_pieces[i].size();
+  }
+
+  return to_underlying_type(status.code());
+}
+unsigned char *spp_nbest_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, size_t nbest) {
   auto sentence_view = absl::string_view(sentence, sentence_len);
-  auto serialized = spp->SampleEncodeAsSerializedProto(sentence_view, static_cast<int>(nbest), alpha);
+  auto serialized = spp->NBestEncodeAsSerializedProto(sentence_view, static_cast<int>(nbest));
 
   *len = serialized.size();
   unsigned char *data = (unsigned char *) malloc(serialized.size());
@@ -87,6 +307,55 @@ unsigned char *spp_sample_encode_as_serialized_proto(SentencePieceProcessor *spp
   return data;
 }
 
+unsigned char *spp_sample_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, int nbest, float alpha) {
+  auto sentence_view = absl::string_view(sentence, sentence_len);
+  auto serialized = spp->SampleEncodeAsSerializedProto(sentence_view, nbest, alpha);
+
+  *len = serialized.size();
+  unsigned char *data = (unsigned char *) malloc(serialized.size());
+  memcpy(data, serialized.data(), serialized.size());
+
+  return data;
+}
+
+unsigned char *spp_sample_encode_and_score_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, int num_samples, float alpha, bool wor, bool include_best) {
+  auto sentence_view = absl::string_view(sentence, sentence_len);
+  auto serialized = spp->SampleEncodeAndScoreAsSerializedProto(sentence_view, num_samples, alpha, wor, include_best);
+
+  *len = serialized.size();
+  unsigned char *data = (unsigned char *) malloc(serialized.size());
+  memcpy(data, serialized.data(), serialized.size());
+
+  return data;
+}
+
+int spp_normalize(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, unsigned char **normalized, size_t *normalized_len, size_t **norm_to_orig, size_t *norm_to_orig_len) {
+  auto sentence_view = absl::string_view(sentence, sentence_len);
+  std::string normalized_string;
+  std::vector<size_t> offsets;
+  auto status = spp->Normalize(sentence_view, &normalized_string, &offsets);
+
+  *normalized_len = normalized_string.size();
+  *normalized = static_cast<unsigned char *>(malloc(normalized_string.size()));
+  memcpy(*normalized, normalized_string.data(), normalized_string.size());
+
+  *norm_to_orig_len = offsets.size();
+  *norm_to_orig = static_cast<size_t *>(malloc(offsets.size() * sizeof(size_t)));
+  memcpy(*norm_to_orig, offsets.data(), offsets.size() * sizeof(size_t));
+
+  return to_underlying_type(status.code());
+}
+
+int spp_set_decode_extra_options(SentencePieceProcessor *spp, char const *extra_options) {
+  auto status = spp->SetDecodeExtraOptions(extra_options);
+  return to_underlying_type(status.code());
+}
+
+int spp_set_encode_extra_options(SentencePieceProcessor *spp, char const *extra_options) {
+  auto status = spp->SetEncodeExtraOptions(extra_options);
+  return to_underlying_type(status.code());
+}
+
 int spp_eos_id(SentencePieceProcessor *spp) {
   return spp->eos_id();
 }
@@ -96,10 +365,58 @@ int spp_load(SentencePieceProcessor *spp, char const *filename) {
   return to_underlying_type(status.code());
 }
 
+int spp_load_vocabulary(SentencePieceProcessor *spp, char const *filename, int threshold) {
+  auto status = spp->LoadVocabulary(filename, threshold);
+  return to_underlying_type(status.code());
+}
+
+int spp_reset_vocabulary(SentencePieceProcessor *spp) {
+  auto status = spp->ResetVocabulary();
+  return to_underlying_type(status.code());
+}
+
+int spp_set_vocabulary(SentencePieceProcessor *spp, char const * const *pieces, size_t pieces_len) {
+    std::vector<absl::string_view> vocab;
+    vocab.reserve(pieces_len);
+
+    for (char const * const *piece = pieces; piece != pieces + pieces_len; ++piece) {
+        vocab.push_back(*piece);
+    }
+
+    auto status = spp->SetVocabulary(vocab);
+    return to_underlying_type(status.code());
+}
+
+float spp_get_score(SentencePieceProcessor *spp, int id) {
+  return spp->GetScore(id);
+}
+
+unsigned char *spp_id_to_piece(SentencePieceProcessor *spp, int id, size_t *len) {
+  auto const &piece = spp->IdToPiece(id);
+
+  *len = piece.size();
+  unsigned char *data = (unsigned char *) malloc(piece.size());
+  memcpy(data, piece.data(), piece.size());
+
+  return data;
+}
+
+bool spp_is_byte(SentencePieceProcessor *spp, int id) {
+  return spp->IsByte(id);
+}
+
+bool spp_is_control(SentencePieceProcessor *spp, int id) {
+  return spp->IsControl(id);
+}
+
 bool spp_is_unknown(SentencePieceProcessor *spp, int id) {
   return spp->IsUnknown(id);
 }
 
+bool spp_is_unused(SentencePieceProcessor *spp, int id) {
+  return spp->IsUnused(id);
+}
+
 int spp_pad_id(SentencePieceProcessor *spp) {
   return spp->pad_id();
 }
@@ -135,4 +452,51 @@ int spp_unk_id(SentencePieceProcessor *spp) {
   return spp->unk_id();
 }
 
+void spp_set_random_generator_seed(unsigned int seed) {
+  sentencepiece::SetRandomGeneratorSeed(seed);
+}
+
+#ifdef SPP_VENDORED
+void *spp_seed_random_generator(unsigned int seed) {
+  auto *generator = sentencepiece::random::GetRandomGenerator();
+  auto *saved = new std::mt19937(*generator);
+  generator->seed(seed);
+  return saved;
+}
+
+void spp_restore_random_generator(void *saved) {
+  auto *saved_generator = static_cast<std::mt19937 *>(saved);
+  *sentencepiece::random::GetRandomGenerator() = *saved_generator;
+  delete saved_generator;
+}
+#endif
+
+void spp_set_log_callback(spp_log_callback callback) {
+  std::cerr.flush();
+  log_buf->set_callback(callback);
+}
+
+void spp_set_min_log_level(int level) {
+  sentencepiece::SetMinLogLevel(level);
+}
+
+int spt_train(char const * const *keys, char const * const *values, size_t len, unsigned char **message, size_t *message_len) {
+  auto status = SentencePieceTrainer::Train(to_kwargs(keys, values, len));
+
+  copy_string(status.error_message(), message, message_len);
+
+  return to_underlying_type(status.code());
+}
+
+int spt_train_from_iterator(char const * const *keys, char const * const *values, size_t len, spt_sentence_callback next_sentence, void *state, unsigned char **model, size_t *model_len, unsigned char **message, size_t *message_len) {
+  CallbackSentenceIterator sentences(next_sentence, state);
+  std::string serialized;
+  auto status = SentencePieceTrainer::Train(to_kwargs(keys, values, len), &sentences, &serialized);
+
+  copy_string(serialized, model, model_len);
+  copy_string(status.error_message(), message, message_len);
+
+  return to_underlying_type(status.code());
+}
+
 }
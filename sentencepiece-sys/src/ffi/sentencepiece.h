@@ -10,30 +10,68 @@ typedef struct SentencePieceProcessor SentencePieceProcessor;
 
 typedef struct SentencePieceText SentencePieceText;
 
+typedef bool (*spt_sentence_callback)(void *state, unsigned char const **sentence, size_t *sentence_len);
+
+typedef void (*spp_log_callback)(unsigned char const *line, size_t line_len);
+
 int spp_decode_piece_ids(SentencePieceProcessor *spp, uint32_t const *pieces, size_t pieces_len, unsigned char **decoded, size_t *decoded_len);
 
 int spp_decode_pieces(SentencePieceProcessor *spp, char const * const *pieces, size_t pieces_len, unsigned char **decoded, size_t *decoded_len);
 
+int spp_decode_piece_ids_as_serialized_proto(SentencePieceProcessor *spp, uint32_t const *pieces, size_t pieces_len, unsigned char **proto, size_t *proto_len);
+
+int spp_decode_pieces_as_serialized_proto(SentencePieceProcessor *spp, char const * const *pieces, size_t pieces_len, unsigned char **proto, size_t *proto_len);
+
 unsigned char *spp_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len);
 
-unsigned char *spp_sample_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, size_t nbest, float alpha);
+int spp_encode_as_ids(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, uint32_t **ids, size_t *ids_len);
+
+int spp_encode_as_pieces(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, unsigned char **pieces, size_t *pieces_len, size_t **piece_lens, size_t *piece_lens_len);
+
+unsigned char *spp_nbest_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, size_t nbest);
+
+unsigned char *spp_sample_encode_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, int nbest, float alpha);
+
+int spp_normalize(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, unsigned char **normalized, size_t *normalized_len, size_t **norm_to_orig, size_t *norm_to_orig_len);
+
+unsigned char *spp_sample_encode_and_score_as_serialized_proto(SentencePieceProcessor *spp, char const *sentence, size_t sentence_len, size_t *len, int num_samples, float alpha, bool wor, bool include_best);
 
 SentencePieceProcessor *spp_new();
 
+int spp_set_decode_extra_options(SentencePieceProcessor *spp, char const *extra_options);
+
+int spp_set_encode_extra_options(SentencePieceProcessor *spp, char const *extra_options);
+
 int spp_from_serialized_proto(SentencePieceProcessor *spp, char const *data, size_t len);
 
 unsigned char *spp_to_serialized_proto(SentencePieceProcessor *spp, size_t *len);
 
 int spp_load(SentencePieceProcessor *spp, char const *filename);
 
+int spp_load_vocabulary(SentencePieceProcessor *spp, char const *filename, int threshold);
+
+int spp_reset_vocabulary(SentencePieceProcessor *spp);
+
+int spp_set_vocabulary(SentencePieceProcessor *spp, char const * const *pieces, size_t pieces_len);
+
 void spp_free(SentencePieceProcessor *spp);
 
 int spp_bos_id(SentencePieceProcessor *spp);
 
 int spp_eos_id(SentencePieceProcessor *spp);
 
+float spp_get_score(SentencePieceProcessor *spp, int id);
+
+unsigned char *spp_id_to_piece(SentencePieceProcessor *spp, int id, size_t *len);
+
+bool spp_is_byte(SentencePieceProcessor *spp, int id);
+
+bool spp_is_control(SentencePieceProcessor *spp, int id);
+
 bool spp_is_unknown(SentencePieceProcessor *spp, int id);
 
+bool spp_is_unused(SentencePieceProcessor *spp, int id);
+
 int spp_pad_id(SentencePieceProcessor *spp);
 
 int spp_piece_to_id(SentencePieceProcessor *spp, char const *piece);
@@ -42,6 +80,22 @@ int spp_piece_size(SentencePieceProcessor *spp);
 
 int spp_unk_id(SentencePieceProcessor *spp);
 
+void spp_set_random_generator_seed(unsigned int seed);
+
+#ifdef SPP_VENDORED
+void *spp_seed_random_generator(unsigned int seed);
+
+void spp_restore_random_generator(void *saved);
+#endif
+
+void spp_set_log_callback(spp_log_callback callback);
+
+void spp_set_min_log_level(int level);
+
+int spt_train(char const * const *keys, char const * const *values, size_t len, unsigned char **message, size_t *message_len);
+
+int spt_train_from_iterator(char const * const *keys, char const * const *values, size_t len, spt_sentence_callback next_sentence, void *state, unsigned char **model, size_t *model_len, unsigned char **message, size_t *message_len);
+
 #ifdef __cplusplus
 }
 #endif
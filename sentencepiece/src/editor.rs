@@ -0,0 +1,203 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use crate::sentencepiece_model::{model_proto, ModelProto, NormalizerSpec};
+use crate::{NormalizationRules, PieceType, SentencePieceError, SentencePieceProcessor};
+
+/// Editor for sentencepiece models.
+///
+/// The editor operates on the protocol buffer representation of a model.
+/// Every edit is validated, the model must not contain duplicate pieces
+/// and must contain exactly one unknown piece. This also applies to
+/// models that are edited after conversion with `ModelEditor::try_from`.
+/// The edited model is loaded using [`build`](Self::build).
+///
+/// Note that removing a piece changes the identifiers of all pieces that
+/// follow it.
+#[derive(Clone, Debug)]
+pub struct ModelEditor {
+    model: ModelProto,
+}
+
+impl ModelEditor {
+    /// Construct an editor for the model of a processor.
+    pub fn new(spp: &SentencePieceProcessor) -> Self {
+        ModelEditor {
+            model: spp.model_proto(),
+        }
+    }
+
+    /// Add a piece to the end of the vocabulary.
+    ///
+    /// Returns the identifier of the added piece.
+    pub fn add_piece(
+        &mut self,
+        piece: impl Into<String>,
+        score: f32,
+        piece_type: PieceType,
+    ) -> Result<u32, SentencePieceError> {
+        check_score(score)?;
+        self.edit_pieces(|pieces| {
+            pieces.push(model_proto::SentencePiece {
+                piece: Some(piece.into()),
+                score: Some(score),
+                r#type: Some(piece_type as i32),
+            })
+        })?;
+
+        Ok(self.model.pieces.len() as u32 - 1)
+    }
+
+    /// Build a processor from the edited model.
+    pub fn build(&self) -> Result<SentencePieceProcessor, SentencePieceError> {
+        SentencePieceProcessor::from_serialized_proto(&prost::Message::encode_to_vec(&self.model))
+    }
+
+    /// Get the edited model.
+    pub fn model_proto(&self) -> &ModelProto {
+        &self.model
+    }
+
+    /// Remove a piece from the vocabulary.
+    ///
+    /// The special piece identifiers of the trainer specification are
+    /// updated to the new identifiers. The identifier of a removed special
+    /// piece is set to `-1`.
+    pub fn remove_piece(&mut self, piece: &str) -> Result<(), SentencePieceError> {
+        let idx = self.find_piece_or_err(piece)?;
+        self.edit_pieces(|pieces| {
+            pieces.remove(idx);
+        })?;
+
+        if let Some(trainer_spec) = self.model.trainer_spec.as_mut() {
+            let update = |id: i32| match id.cmp(&(idx as i32)) {
+                Ordering::Less => id,
+                Ordering::Equal => -1,
+                Ordering::Greater => id - 1,
+            };
+            trainer_spec.unk_id = Some(update(trainer_spec.unk_id()));
+            trainer_spec.bos_id = Some(update(trainer_spec.bos_id()));
+            trainer_spec.eos_id = Some(update(trainer_spec.eos_id()));
+            trainer_spec.pad_id = Some(update(trainer_spec.pad_id()));
+        }
+
+        Ok(())
+    }
+
+    /// Replace the normalization rules.
+    ///
+    /// The rules are compiled into the precompiled character map of the
+    /// normalizer specification, the other normalizer settings are
+    /// retained.
+    pub fn set_normalization_rules(
+        &mut self,
+        rules: &NormalizationRules,
+    ) -> Result<(), SentencePieceError> {
+        let charsmap = rules.to_precompiled_charsmap()?;
+
+        let normalizer_spec = self
+            .model
+            .normalizer_spec
+            .get_or_insert_with(NormalizerSpec::default);
+        normalizer_spec.name = Some("user_defined".to_string());
+        normalizer_spec.precompiled_charsmap = Some(charsmap);
+        normalizer_spec.normalization_rule_tsv = None;
+
+        Ok(())
+    }
+
+    /// Replace the normalizer specification.
+    pub fn set_normalizer_spec(&mut self, normalizer_spec: NormalizerSpec) {
+        self.model.normalizer_spec = Some(normalizer_spec);
+    }
+
+    /// Change the type of a piece.
+    pub fn set_piece_type(
+        &mut self,
+        piece: &str,
+        piece_type: PieceType,
+    ) -> Result<(), SentencePieceError> {
+        let idx = self.find_piece_or_err(piece)?;
+        self.edit_pieces(|pieces| pieces[idx].r#type = Some(piece_type as i32))
+    }
+
+    /// Change the score of a piece.
+    ///
+    /// The score must be finite.
+    pub fn set_score(&mut self, piece: &str, score: f32) -> Result<(), SentencePieceError> {
+        check_score(score)?;
+        let idx = self.find_piece_or_err(piece)?;
+        self.model.pieces[idx].score = Some(score);
+        Ok(())
+    }
+
+    /// Apply an edit to the pieces, the edit is only retained when the
+    /// resulting pieces are valid.
+    fn edit_pieces<F>(&mut self, edit: F) -> Result<(), SentencePieceError>
+    where
+        F: FnOnce(&mut Vec<model_proto::SentencePiece>),
+    {
+        let mut pieces = self.model.pieces.clone();
+        edit(&mut pieces);
+        validate_pieces(&pieces)?;
+
+        self.model.pieces = pieces;
+
+        Ok(())
+    }
+
+    fn find_piece(&self, piece: &str) -> Option<usize> {
+        self.model
+            .pieces
+            .iter()
+            .position(|p| p.piece.as_deref() == Some(piece))
+    }
+
+    fn find_piece_or_err(&self, piece: &str) -> Result<usize, SentencePieceError> {
+        self.find_piece(piece)
+            .ok_or_else(|| SentencePieceError::PieceNotFound(piece.to_owned()))
+    }
+}
+
+impl TryFrom<ModelProto> for ModelEditor {
+    type Error = SentencePieceError;
+
+    /// Construct an editor for a model, the pieces of the model are
+    /// validated.
+    fn try_from(model: ModelProto) -> Result<Self, Self::Error> {
+        validate_pieces(&model.pieces)?;
+        Ok(ModelEditor { model })
+    }
+}
+
+/// Check that a piece score is finite.
+fn check_score(score: f32) -> Result<(), SentencePieceError> {
+    if !score.is_finite() {
+        return Err(SentencePieceError::InvalidScore);
+    }
+
+    Ok(())
+}
+
+/// Check that the pieces do not contain duplicates and contain exactly
+/// one unknown piece.
+fn validate_pieces(pieces: &[model_proto::SentencePiece]) -> Result<(), SentencePieceError> {
+    let mut seen = HashSet::new();
+    for piece in pieces {
+        let piece = piece.piece();
+        if !seen.insert(piece) {
+            return Err(SentencePieceError::DuplicatePiece(piece.to_owned()));
+        }
+    }
+
+    let unk_count = pieces
+        .iter()
+        .filter(|piece| piece.r#type == Some(PieceType::Unknown as i32))
+        .count();
+    if unk_count != 1 {
+        return Err(SentencePieceError::UnknownPieceCount(unk_count));
+    }
+
+    Ok(())
+}
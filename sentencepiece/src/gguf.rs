@@ -0,0 +1,498 @@
+//! Tokenizer metadata in the GGUF format.
+//!
+//! GGUF files store the tokenizer of llama.cpp-style models as
+//! `tokenizer.ggml.*` metadata entries. This module converts the
+//! vocabulary of a sentencepiece model to these entries and writes
+//! them as a GGUF file that contains no tensors. Conversely, it reads
+//! the metadata of GGUF files and reconstructs a sentencepiece model
+//! from the tokenizer entries.
+
+use std::io::{self, Read, Write};
+
+use crate::sentencepiece_model::{
+    model_proto, trainer_spec, ModelProto, NormalizerSpec, TrainerSpec,
+};
+use crate::{EncodeOptions, ModelType, PieceType, SentencePieceError, SentencePieceProcessor};
+
+const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+const GGUF_VERSION: u32 = 3;
+const GGUF_DEFAULT_ALIGNMENT: usize = 32;
+
+/// Tokenizer model of sentencepiece vocabularies in GGUF files.
+const LLAMA_TOKENIZER_MODEL: &str = "llama";
+
+/// Value of a GGUF metadata entry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum GgufValue {
+    UInt8(u8),
+    Int8(i8),
+    UInt16(u16),
+    Int16(i16),
+    UInt32(u32),
+    Int32(i32),
+    Float32(f32),
+    Bool(bool),
+    String(String),
+
+    /// Array of values, all values must have the same type. As in
+    /// llama.cpp, arrays cannot be nested.
+    Array(Vec<GgufValue>),
+
+    UInt64(u64),
+    Int64(i64),
+    Float64(f64),
+}
+
+impl GgufValue {
+    /// The type of the value in the GGUF format.
+    fn value_type(&self) -> u32 {
+        match self {
+            GgufValue::UInt8(_) => 0,
+            GgufValue::Int8(_) => 1,
+            GgufValue::UInt16(_) => 2,
+            GgufValue::Int16(_) => 3,
+            GgufValue::UInt32(_) => 4,
+            GgufValue::Int32(_) => 5,
+            GgufValue::Float32(_) => 6,
+            GgufValue::Bool(_) => 7,
+            GgufValue::String(_) => 8,
+            GgufValue::Array(_) => 9,
+            GgufValue::UInt64(_) => 10,
+            GgufValue::Int64(_) => 11,
+            GgufValue::Float64(_) => 12,
+        }
+    }
+
+    fn read_from(read: &mut impl Read, value_type: u32) -> Result<Self, SentencePieceError> {
+        Ok(match value_type {
+            0 => GgufValue::UInt8(u8::from_le_bytes(read_bytes(read)?)),
+            1 => GgufValue::Int8(i8::from_le_bytes(read_bytes(read)?)),
+            2 => GgufValue::UInt16(u16::from_le_bytes(read_bytes(read)?)),
+            3 => GgufValue::Int16(i16::from_le_bytes(read_bytes(read)?)),
+            4 => GgufValue::UInt32(u32::from_le_bytes(read_bytes(read)?)),
+            5 => GgufValue::Int32(i32::from_le_bytes(read_bytes(read)?)),
+            6 => GgufValue::Float32(f32::from_le_bytes(read_bytes(read)?)),
+            7 => match read_bytes::<1>(read)? {
+                [0] => GgufValue::Bool(false),
+                [1] => GgufValue::Bool(true),
+                [v] => return Err(invalid_gguf(format!("invalid boolean value {}", v))),
+            },
+            8 => GgufValue::String(read_string(read)?),
+            9 => {
+                let value_type = u32::from_le_bytes(read_bytes(read)?);
+                if value_type == 9 {
+                    return Err(invalid_gguf("nested arrays are not supported"));
+                }
+                let len = u64::from_le_bytes(read_bytes(read)?);
+                // Do not trust the length for preallocation.
+                let mut values = Vec::with_capacity(len.min(1 << 16) as usize);
+                for _ in 0..len {
+                    values.push(GgufValue::read_from(read, value_type)?);
+                }
+                GgufValue::Array(values)
+            }
+            10 => GgufValue::UInt64(u64::from_le_bytes(read_bytes(read)?)),
+            11 => GgufValue::Int64(i64::from_le_bytes(read_bytes(read)?)),
+            12 => GgufValue::Float64(f64::from_le_bytes(read_bytes(read)?)),
+            value_type => return Err(invalid_gguf(format!("unknown value type {}", value_type))),
+        })
+    }
+
+    fn write_to(&self, write: &mut impl Write) -> io::Result<()> {
+        match self {
+            GgufValue::UInt8(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Int8(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::UInt16(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Int16(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::UInt32(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Int32(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Float32(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Bool(v) => write.write_all(&[*v as u8]),
+            GgufValue::String(v) => write_string(write, v),
+            GgufValue::Array(values) => {
+                // Empty arrays do not have an element type, use u8.
+                let value_type = values.first().map(GgufValue::value_type).unwrap_or(0);
+                if values.iter().any(|v| v.value_type() != value_type) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "GGUF array values must have the same type",
+                    ));
+                }
+                if value_type == 9 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "GGUF arrays cannot be nested",
+                    ));
+                }
+
+                write.write_all(&value_type.to_le_bytes())?;
+                write.write_all(&(values.len() as u64).to_le_bytes())?;
+                for value in values {
+                    value.write_to(write)?;
+                }
+
+                Ok(())
+            }
+            GgufValue::UInt64(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Int64(v) => write.write_all(&v.to_le_bytes()),
+            GgufValue::Float64(v) => write.write_all(&v.to_le_bytes()),
+        }
+    }
+}
+
+fn invalid_gguf(message: impl Into<String>) -> SentencePieceError {
+    SentencePieceError::InvalidGguf(message.into())
+}
+
+fn read_bytes<const N: usize>(read: &mut impl Read) -> Result<[u8; N], SentencePieceError> {
+    let mut bytes = [0; N];
+    read.read_exact(&mut bytes)
+        .map_err(|err| invalid_gguf(err.to_string()))?;
+    Ok(bytes)
+}
+
+fn read_string(read: &mut impl Read) -> Result<String, SentencePieceError> {
+    let len = u64::from_le_bytes(read_bytes(read)?);
+    let mut data = Vec::new();
+    read.take(len)
+        .read_to_end(&mut data)
+        .map_err(|err| invalid_gguf(err.to_string()))?;
+    if data.len() as u64 != len {
+        return Err(invalid_gguf("unexpected end of file"));
+    }
+
+    String::from_utf8(data).map_err(|_| invalid_gguf("string is not valid UTF-8"))
+}
+
+fn write_string(write: &mut impl Write, s: &str) -> io::Result<()> {
+    write.write_all(&(s.len() as u64).to_le_bytes())?;
+    write.write_all(s.as_bytes())
+}
+
+/// Get the GGUF tokenizer metadata of a sentencepiece model.
+///
+/// The metadata uses the `llama` tokenizer model. `add_bos` and `add_eos`
+/// set whether runtimes should add the begin/end of sentence pieces. The
+/// corresponding keys are omitted when they are `None`, leaving the choice
+/// to the runtime. Only unigram and BPE models can be converted.
+///
+/// Note that llama.cpp-style runtimes segment text by merging pieces in
+/// order of their scores and do not apply the normalization rules of the
+/// sentencepiece model.
+pub fn tokenizer_metadata(
+    spp: &SentencePieceProcessor,
+    add_bos: Option<bool>,
+    add_eos: Option<bool>,
+) -> Result<Vec<(String, GgufValue)>, SentencePieceError> {
+    match spp.model_type() {
+        ModelType::Unigram | ModelType::Bpe => (),
+        model_type => return Err(SentencePieceError::UnsupportedConversion(model_type)),
+    }
+
+    let model = spp.model_proto();
+
+    let tokens = model
+        .pieces
+        .iter()
+        .map(|piece| GgufValue::String(piece.piece().to_owned()))
+        .collect();
+    let scores = model
+        .pieces
+        .iter()
+        .map(|piece| GgufValue::Float32(piece.score()))
+        .collect();
+    let token_types = model
+        .pieces
+        .iter()
+        .map(|piece| {
+            let piece_type = piece
+                .r#type
+                .and_then(PieceType::from_proto)
+                .unwrap_or(PieceType::Normal);
+            GgufValue::Int32(piece_type as i32)
+        })
+        .collect();
+    let add_space_prefix = model
+        .normalizer_spec
+        .as_ref()
+        .map(|spec| spec.add_dummy_prefix())
+        .unwrap_or(true);
+
+    let mut metadata = vec![
+        (
+            "tokenizer.ggml.model".to_string(),
+            GgufValue::String(LLAMA_TOKENIZER_MODEL.to_string()),
+        ),
+        (
+            "tokenizer.ggml.tokens".to_string(),
+            GgufValue::Array(tokens),
+        ),
+        (
+            "tokenizer.ggml.scores".to_string(),
+            GgufValue::Array(scores),
+        ),
+        (
+            "tokenizer.ggml.token_type".to_string(),
+            GgufValue::Array(token_types),
+        ),
+    ];
+
+    let special_ids = [
+        ("tokenizer.ggml.bos_token_id", spp.bos_id()),
+        ("tokenizer.ggml.eos_token_id", spp.eos_id()),
+        ("tokenizer.ggml.unknown_token_id", Some(spp.unk_id())),
+        ("tokenizer.ggml.padding_token_id", spp.pad_id()),
+    ];
+    for (key, id) in special_ids {
+        if let Some(id) = id {
+            metadata.push((key.to_string(), GgufValue::UInt32(id)));
+        }
+    }
+
+    let add_special = [
+        ("tokenizer.ggml.add_bos_token", add_bos),
+        ("tokenizer.ggml.add_eos_token", add_eos),
+    ];
+    for (key, add) in add_special {
+        if let Some(add) = add {
+            metadata.push((key.to_string(), GgufValue::Bool(add)));
+        }
+    }
+
+    metadata.push((
+        "tokenizer.ggml.add_space_prefix".to_string(),
+        GgufValue::Bool(add_space_prefix),
+    ));
+
+    Ok(metadata)
+}
+
+/// Write a GGUF file with the given metadata and without tensors.
+pub fn write_gguf(metadata: &[(String, GgufValue)], mut write: impl Write) -> io::Result<()> {
+    let mut data = Vec::new();
+    data.extend_from_slice(GGUF_MAGIC);
+    data.extend_from_slice(&GGUF_VERSION.to_le_bytes());
+    // Number of tensors.
+    data.extend_from_slice(&0u64.to_le_bytes());
+    data.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+
+    for (key, value) in metadata {
+        write_string(&mut data, key)?;
+        data.extend_from_slice(&value.value_type().to_le_bytes());
+        value.write_to(&mut data)?;
+    }
+
+    // The (empty) tensor data starts at an aligned offset.
+    let padded_len = data.len().div_ceil(GGUF_DEFAULT_ALIGNMENT) * GGUF_DEFAULT_ALIGNMENT;
+    data.resize(padded_len, 0);
+
+    write.write_all(&data)
+}
+
+/// Read the metadata of a GGUF file.
+///
+/// Only the metadata is read, tensor information and data are skipped.
+pub fn read_gguf(mut read: impl Read) -> Result<Vec<(String, GgufValue)>, SentencePieceError> {
+    if &read_bytes::<4>(&mut read)? != GGUF_MAGIC {
+        return Err(invalid_gguf("file does not start with GGUF magic"));
+    }
+
+    // Version 1 used 32-bit lengths, later versions have the same layout.
+    let version = u32::from_le_bytes(read_bytes(&mut read)?);
+    if !(2..=GGUF_VERSION).contains(&version) {
+        return Err(invalid_gguf(format!("unsupported version {}", version)));
+    }
+
+    let _tensor_count = u64::from_le_bytes(read_bytes(&mut read)?);
+    let metadata_count = u64::from_le_bytes(read_bytes(&mut read)?);
+
+    let mut metadata = Vec::with_capacity(metadata_count.min(1 << 10) as usize);
+    for _ in 0..metadata_count {
+        let key = read_string(&mut read)?;
+        let value_type = u32::from_le_bytes(read_bytes(&mut read)?);
+        let value = GgufValue::read_from(&mut read, value_type)?;
+        metadata.push((key, value));
+    }
+
+    Ok(metadata)
+}
+
+/// Construct a sentencepiece model from GGUF tokenizer metadata.
+///
+/// The metadata must use the `llama` tokenizer model. Since llama.cpp-style
+/// runtimes segment text by merging pieces in order of their scores, the
+/// model is reconstructed as a BPE model without normalization rules, so
+/// that it produces the same identifiers as the runtime. Whether begin/end
+/// of sentence pieces are added is set in the
+/// [`EncodeOptions`](crate::EncodeOptions) of the processor.
+pub fn tokenizer_from_metadata(
+    metadata: &[(String, GgufValue)],
+) -> Result<SentencePieceProcessor, SentencePieceError> {
+    let get = |key: &str| {
+        metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value)
+    };
+    let get_required =
+        |key: &str| get(key).ok_or_else(|| SentencePieceError::MissingMetadata(key.to_string()));
+    let inconsistent = |message: String| SentencePieceError::InconsistentMetadata(message);
+
+    match get_required("tokenizer.ggml.model")? {
+        GgufValue::String(model) if model == LLAMA_TOKENIZER_MODEL => (),
+        GgufValue::String(model) => {
+            return Err(SentencePieceError::UnsupportedTokenizer(format!(
+                "tokenizer model {}",
+                model
+            )))
+        }
+        _ => {
+            return Err(inconsistent(
+                "tokenizer.ggml.model is not a string".to_string(),
+            ))
+        }
+    }
+
+    let tokens = array(
+        get_required("tokenizer.ggml.tokens")?,
+        "tokenizer.ggml.tokens",
+    )?
+    .iter()
+    .map(|token| match token {
+        GgufValue::String(token) => Ok(token.clone()),
+        _ => Err(inconsistent(
+            "tokenizer.ggml.tokens contains a non-string value".to_string(),
+        )),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+    let scores = array(
+        get_required("tokenizer.ggml.scores")?,
+        "tokenizer.ggml.scores",
+    )?
+    .iter()
+    .map(|score| match score {
+        GgufValue::Float32(score) => Ok(*score),
+        _ => Err(inconsistent(
+            "tokenizer.ggml.scores contains a non-float value".to_string(),
+        )),
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+    if scores.len() != tokens.len() {
+        return Err(inconsistent(format!(
+            "{} tokens, but {} scores",
+            tokens.len(),
+            scores.len()
+        )));
+    }
+
+    let piece_types = match get("tokenizer.ggml.token_type") {
+        Some(token_types) => array(token_types, "tokenizer.ggml.token_type")?
+            .iter()
+            .map(|token_type| match token_type {
+                GgufValue::Int32(token_type) => PieceType::from_proto(*token_type)
+                    .ok_or_else(|| inconsistent(format!("unknown token type {}", token_type))),
+                _ => Err(inconsistent(
+                    "tokenizer.ggml.token_type contains a non-integer value".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => vec![PieceType::Normal; tokens.len()],
+    };
+    if piece_types.len() != tokens.len() {
+        return Err(inconsistent(format!(
+            "{} tokens, but {} token types",
+            tokens.len(),
+            piece_types.len()
+        )));
+    }
+
+    let mut pieces = tokens
+        .into_iter()
+        .zip(scores)
+        .zip(piece_types)
+        .map(|((piece, score), piece_type)| model_proto::SentencePiece {
+            piece: Some(piece),
+            score: Some(score),
+            r#type: Some(piece_type as i32),
+        })
+        .collect::<Vec<_>>();
+
+    let mut special_piece = |key: &str, piece_type: PieceType| match get(key) {
+        Some(GgufValue::UInt32(id)) => {
+            let piece = pieces
+                .get_mut(*id as usize)
+                .ok_or_else(|| inconsistent(format!("{} is out of range: {}", key, id)))?;
+            piece.r#type = Some(piece_type as i32);
+            Ok(piece.piece.clone())
+        }
+        Some(_) => Err(inconsistent(format!("{} is not an unsigned integer", key))),
+        None => Ok(None),
+    };
+
+    let unk_piece = special_piece("tokenizer.ggml.unknown_token_id", PieceType::Unknown)?;
+    let bos_piece = special_piece("tokenizer.ggml.bos_token_id", PieceType::Control)?;
+    let eos_piece = special_piece("tokenizer.ggml.eos_token_id", PieceType::Control)?;
+    let pad_piece = special_piece("tokenizer.ggml.padding_token_id", PieceType::Control)?;
+
+    let unk_id = pieces
+        .iter()
+        .position(|piece| piece.r#type == Some(PieceType::Unknown as i32))
+        .ok_or_else(|| inconsistent("vocabulary does not have an unknown token".to_string()))?;
+    let byte_fallback = pieces
+        .iter()
+        .any(|piece| piece.r#type == Some(PieceType::Byte as i32));
+
+    let model = ModelProto {
+        trainer_spec: Some(TrainerSpec {
+            model_type: Some(trainer_spec::ModelType::Bpe as i32),
+            vocab_size: Some(pieces.len() as i32),
+            byte_fallback: Some(byte_fallback),
+            unk_id: Some(unk_id as i32),
+            unk_piece: unk_piece.or_else(|| pieces[unk_id].piece.clone()),
+            bos_piece,
+            eos_piece,
+            pad_piece,
+            ..Default::default()
+        }),
+        pieces,
+        normalizer_spec: Some(NormalizerSpec {
+            name: Some("identity".to_string()),
+            add_dummy_prefix: Some(bool_metadata(get("tokenizer.ggml.add_space_prefix"), true)?),
+            remove_extra_whitespaces: Some(false),
+            escape_whitespaces: Some(true),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let mut spp =
+        SentencePieceProcessor::from_serialized_proto(&prost::Message::encode_to_vec(&model))?;
+    spp.set_encode_options(EncodeOptions {
+        add_bos: bool_metadata(get("tokenizer.ggml.add_bos_token"), false)?,
+        add_eos: bool_metadata(get("tokenizer.ggml.add_eos_token"), false)?,
+        ..Default::default()
+    })?;
+
+    Ok(spp)
+}
+
+fn array<'a>(value: &'a GgufValue, key: &str) -> Result<&'a [GgufValue], SentencePieceError> {
+    match value {
+        GgufValue::Array(values) => Ok(values),
+        _ => Err(SentencePieceError::InconsistentMetadata(format!(
+            "{} is not an array",
+            key
+        ))),
+    }
+}
+
+fn bool_metadata(value: Option<&GgufValue>, default: bool) -> Result<bool, SentencePieceError> {
+    match value {
+        Some(GgufValue::Bool(value)) => Ok(*value),
+        Some(_) => Err(SentencePieceError::InconsistentMetadata(
+            "boolean metadata has a non-boolean value".to_string(),
+        )),
+        None => Ok(default),
+    }
+}
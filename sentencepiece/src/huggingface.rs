@@ -0,0 +1,486 @@
+//! Conversion between sentencepiece models and HuggingFace `tokenizer.json`
+//! files.
+//!
+//! The conversion follows the `tokenizers` library's converters for
+//! sentencepiece models: unigram models are converted to a `Unigram`
+//! model and BPE models to a `BPE` model with merges that are derived
+//! from the vocabulary. Normalization is expressed with the
+//! `Precompiled` normalizer and the whitespace handling of
+//! sentencepiece with the `Metaspace` pre-tokenizer and decoder.
+
+use std::collections::BTreeMap;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::sentencepiece_model::{
+    model_proto, trainer_spec, ModelProto, NormalizerSpec, TrainerSpec,
+};
+use crate::{ModelType, PieceType, SentencePieceError, SentencePieceProcessor};
+
+/// Meta symbol that sentencepiece uses to represent whitespace.
+const META_SYMBOL: &str = "\u{2581}";
+
+/// Convert a sentencepiece model to a HuggingFace `tokenizer.json`.
+///
+/// Only unigram and BPE models can be converted. The identifiers in the
+/// resulting tokenizer are the same as the identifiers of the model.
+pub fn to_tokenizer_json(spp: &SentencePieceProcessor) -> Result<String, SentencePieceError> {
+    let model = spp.model_proto();
+    let model_type = spp.model_type();
+
+    let (model_json, byte_fallback) = match model_type {
+        ModelType::Unigram => unigram_model(&model, spp.unk_id()),
+        ModelType::Bpe => bpe_model(&model, spp.unk_id()),
+        model_type => return Err(SentencePieceError::UnsupportedConversion(model_type)),
+    };
+
+    let add_dummy_prefix = model
+        .normalizer_spec
+        .as_ref()
+        .map(NormalizerSpec::add_dummy_prefix)
+        .unwrap_or(true);
+    let metaspace = json!({
+        "type": "Metaspace",
+        "replacement": META_SYMBOL,
+        "prepend_scheme": if add_dummy_prefix { "always" } else { "never" },
+        "split": true,
+    });
+
+    let decoder = if byte_fallback {
+        json!({
+            "type": "Sequence",
+            "decoders": [{ "type": "ByteFallback" }, metaspace],
+        })
+    } else {
+        metaspace.clone()
+    };
+
+    let tokenizer = json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": added_tokens(&model),
+        "normalizer": normalizer(model.normalizer_spec.as_ref()),
+        "pre_tokenizer": metaspace,
+        "post_processor": null,
+        "decoder": decoder,
+        "model": model_json,
+    });
+
+    Ok(serde_json::to_string_pretty(&tokenizer).expect("Cannot serialize tokenizer JSON"))
+}
+
+fn piece_type(piece: &model_proto::SentencePiece) -> PieceType {
+    piece
+        .r#type
+        .and_then(PieceType::from_proto)
+        .unwrap_or(PieceType::Normal)
+}
+
+/// Tokens that should not be split by the model.
+fn added_tokens(model: &ModelProto) -> Vec<Value> {
+    model
+        .pieces
+        .iter()
+        .enumerate()
+        .filter_map(|(id, piece)| {
+            let special = match piece_type(piece) {
+                PieceType::Control | PieceType::Unknown => true,
+                PieceType::UserDefined => false,
+                _ => return None,
+            };
+
+            Some(json!({
+                "id": id,
+                "content": piece.piece(),
+                "single_word": false,
+                "lstrip": false,
+                "rstrip": false,
+                "normalized": false,
+                "special": special,
+            }))
+        })
+        .collect()
+}
+
+fn normalizer(normalizer_spec: Option<&NormalizerSpec>) -> Value {
+    let normalizer_spec = match normalizer_spec {
+        Some(normalizer_spec) => normalizer_spec,
+        None => return Value::Null,
+    };
+
+    let mut normalizers = Vec::new();
+
+    if let Some(charsmap) = normalizer_spec
+        .precompiled_charsmap
+        .as_ref()
+        .filter(|charsmap| !charsmap.is_empty())
+    {
+        normalizers.push(json!({
+            "type": "Precompiled",
+            "precompiled_charsmap": BASE64.encode(charsmap),
+        }));
+    }
+
+    if normalizer_spec.remove_extra_whitespaces() {
+        normalizers.push(json!({
+            "type": "Strip",
+            "strip_left": true,
+            "strip_right": true,
+        }));
+        normalizers.push(json!({
+            "type": "Replace",
+            "pattern": { "Regex": " {2,}" },
+            "content": " ",
+        }));
+    }
+
+    if normalizers.is_empty() {
+        Value::Null
+    } else {
+        json!({
+            "type": "Sequence",
+            "normalizers": normalizers,
+        })
+    }
+}
+
+fn byte_fallback(model: &ModelProto) -> bool {
+    model
+        .trainer_spec
+        .as_ref()
+        .map(|spec| spec.byte_fallback())
+        .unwrap_or(false)
+}
+
+fn unigram_model(model: &ModelProto, unk_id: u32) -> (Value, bool) {
+    let vocab = model
+        .pieces
+        .iter()
+        .map(|piece| json!([piece.piece(), piece.score()]))
+        .collect::<Vec<_>>();
+    let byte_fallback = byte_fallback(model);
+
+    (
+        json!({
+            "type": "Unigram",
+            "unk_id": unk_id,
+            "vocab": vocab,
+            "byte_fallback": byte_fallback,
+        }),
+        byte_fallback,
+    )
+}
+
+fn bpe_model(model: &ModelProto, unk_id: u32) -> (Value, bool) {
+    let vocab = model
+        .pieces
+        .iter()
+        .enumerate()
+        .map(|(id, piece)| (piece.piece(), id))
+        .collect::<BTreeMap<_, _>>();
+
+    // Derive the merges from the vocabulary. Every normal piece that can be
+    // split into two pieces of the vocabulary is the result of a merge. The
+    // merges are ordered by the identifier of the merged piece, since BPE
+    // models add pieces in merge order.
+    let mut merges = Vec::new();
+    for (id, piece) in model.pieces.iter().enumerate() {
+        if piece_type(piece) != PieceType::Normal {
+            continue;
+        }
+
+        let piece = piece.piece();
+        for (split, _) in piece.char_indices().skip(1) {
+            let (left, right) = piece.split_at(split);
+            if let (Some(&left_id), Some(&right_id)) = (vocab.get(left), vocab.get(right)) {
+                merges.push((id, left_id, right_id, left, right));
+            }
+        }
+    }
+    merges.sort_unstable_by_key(|&(id, left_id, right_id, _, _)| (id, left_id, right_id));
+
+    let merges = merges
+        .into_iter()
+        .map(|(_, _, _, left, right)| format!("{} {}", left, right))
+        .collect::<Vec<_>>();
+    let byte_fallback = byte_fallback(model);
+
+    (
+        json!({
+            "type": "BPE",
+            "dropout": null,
+            "unk_token": model.pieces[unk_id as usize].piece(),
+            "continuing_subword_prefix": null,
+            "end_of_word_suffix": null,
+            "fuse_unk": true,
+            "byte_fallback": byte_fallback,
+            "vocab": vocab,
+            "merges": merges,
+        }),
+        byte_fallback,
+    )
+}
+
+/// Construct a sentencepiece model from a HuggingFace `tokenizer.json`.
+///
+/// Only tokenizers with a `Unigram` model, such as tokenizers that were
+/// converted from sentencepiece models, are supported. The normalizer must
+/// be expressible as a sentencepiece normalizer, which is the case for
+/// a `Precompiled` normalizer, optionally followed by whitespace
+/// stripping. The pre-tokenizer must be a `Metaspace` pre-tokenizer.
+/// Tokenizers with other components result in an error, since they would
+/// not tokenize the same way as the sentencepiece model.
+pub fn from_tokenizer_json(json: &str) -> Result<SentencePieceProcessor, SentencePieceError> {
+    let tokenizer: Value = serde_json::from_str(json)
+        .map_err(|err| SentencePieceError::InvalidTokenizerJson(err.to_string()))?;
+
+    let model = tokenizer_model_proto(&tokenizer)?;
+
+    SentencePieceProcessor::from_serialized_proto(&prost::Message::encode_to_vec(&model))
+}
+
+fn invalid(message: impl Into<String>) -> SentencePieceError {
+    SentencePieceError::InvalidTokenizerJson(message.into())
+}
+
+fn unsupported(message: impl Into<String>) -> SentencePieceError {
+    SentencePieceError::UnsupportedTokenizer(message.into())
+}
+
+fn component_type(component: &Value) -> &str {
+    component["type"].as_str().unwrap_or("<missing type>")
+}
+
+fn tokenizer_model_proto(tokenizer: &Value) -> Result<ModelProto, SentencePieceError> {
+    let model = &tokenizer["model"];
+    match component_type(model) {
+        "Unigram" => (),
+        model_type => return Err(unsupported(format!("model type {}", model_type))),
+    }
+
+    let unk_id = model["unk_id"]
+        .as_u64()
+        .ok_or_else(|| unsupported("Unigram model without unknown token"))?
+        as usize;
+    let byte_fallback = model["byte_fallback"].as_bool().unwrap_or(false);
+
+    let mut pieces = model["vocab"]
+        .as_array()
+        .ok_or_else(|| invalid("Unigram vocabulary is not an array"))?
+        .iter()
+        .map(|entry| {
+            let piece = entry[0]
+                .as_str()
+                .ok_or_else(|| invalid("vocabulary entry without piece"))?;
+            let score = entry[1]
+                .as_f64()
+                .ok_or_else(|| invalid("vocabulary entry without score"))?;
+
+            let piece_type = if byte_fallback && is_byte_piece(piece) {
+                PieceType::Byte
+            } else {
+                PieceType::Normal
+            };
+
+            Ok(model_proto::SentencePiece {
+                piece: Some(piece.to_owned()),
+                score: Some(score as f32),
+                r#type: Some(piece_type as i32),
+            })
+        })
+        .collect::<Result<Vec<_>, SentencePieceError>>()?;
+
+    if let Some(added_tokens) = tokenizer["added_tokens"].as_array() {
+        for token in added_tokens {
+            add_token(&mut pieces, token)?;
+        }
+    }
+
+    let unk_piece = pieces
+        .get_mut(unk_id)
+        .ok_or_else(|| invalid("unknown token identifier is out of range"))?;
+    unk_piece.r#type = Some(PieceType::Unknown as i32);
+    unk_piece.score = Some(0.);
+    let unk_piece = unk_piece.piece.clone();
+
+    let mut normalizer_spec = normalizer_spec(&tokenizer["normalizer"])?;
+    normalizer_spec.add_dummy_prefix = Some(add_dummy_prefix(&tokenizer["pre_tokenizer"])?);
+
+    Ok(ModelProto {
+        trainer_spec: Some(TrainerSpec {
+            model_type: Some(trainer_spec::ModelType::Unigram as i32),
+            vocab_size: Some(pieces.len() as i32),
+            byte_fallback: Some(byte_fallback),
+            unk_id: Some(unk_id as i32),
+            unk_piece,
+            ..Default::default()
+        }),
+        pieces,
+        normalizer_spec: Some(normalizer_spec),
+        ..Default::default()
+    })
+}
+
+fn is_byte_piece(piece: &str) -> bool {
+    piece.len() == 6
+        && piece.starts_with("<0x")
+        && piece.ends_with('>')
+        && u8::from_str_radix(&piece[3..5], 16).is_ok()
+}
+
+/// Mark an added token as a control or user-defined piece.
+///
+/// Added tokens that are not in the vocabulary of the model must directly
+/// follow the vocabulary.
+fn add_token(
+    pieces: &mut Vec<model_proto::SentencePiece>,
+    token: &Value,
+) -> Result<(), SentencePieceError> {
+    let id = token["id"]
+        .as_u64()
+        .ok_or_else(|| invalid("added token without identifier"))? as usize;
+    let content = token["content"]
+        .as_str()
+        .ok_or_else(|| invalid("added token without content"))?;
+    let piece_type = if token["special"].as_bool().unwrap_or(false) {
+        PieceType::Control
+    } else {
+        PieceType::UserDefined
+    };
+
+    if id == pieces.len() {
+        pieces.push(model_proto::SentencePiece {
+            piece: Some(content.to_owned()),
+            score: Some(0.),
+            r#type: None,
+        });
+    }
+
+    let piece = pieces
+        .get_mut(id)
+        .ok_or_else(|| unsupported(format!("added token {} is not in the vocabulary", content)))?;
+    if piece.piece() != content {
+        return Err(invalid(format!(
+            "added token {} has the identifier of {}",
+            content,
+            piece.piece()
+        )));
+    }
+
+    piece.r#type = Some(piece_type as i32);
+    if piece_type == PieceType::Control {
+        piece.score = Some(0.);
+    }
+
+    Ok(())
+}
+
+fn normalizer_spec(normalizer: &Value) -> Result<NormalizerSpec, SentencePieceError> {
+    let normalizers = match component_type(normalizer) {
+        _ if normalizer.is_null() => Vec::new(),
+        "Sequence" => normalizer["normalizers"]
+            .as_array()
+            .ok_or_else(|| invalid("normalizer sequence is not an array"))?
+            .iter()
+            .collect(),
+        _ => vec![normalizer],
+    };
+
+    let mut precompiled_charsmap = None;
+    let mut strip = false;
+    let mut collapse_whitespace = false;
+    for normalizer in normalizers {
+        match component_type(normalizer) {
+            "Precompiled" if precompiled_charsmap.is_none() => {
+                let charsmap = normalizer["precompiled_charsmap"]
+                    .as_str()
+                    .ok_or_else(|| invalid("precompiled normalizer without charsmap"))?;
+                precompiled_charsmap = Some(
+                    BASE64
+                        .decode(charsmap)
+                        .map_err(|_| invalid("precompiled charsmap is not valid base64"))?,
+                );
+            }
+            // sentencepiece always strips both sides.
+            "Strip" if normalizer["strip_left"] == true && normalizer["strip_right"] == true => {
+                strip = true
+            }
+            "Replace"
+                if normalizer["pattern"]["Regex"] == " {2,}"
+                    && (normalizer["content"] == " " || normalizer["content"] == META_SYMBOL) =>
+            {
+                collapse_whitespace = true
+            }
+            normalizer_type => {
+                return Err(unsupported(format!("normalizer {}", normalizer_type)));
+            }
+        }
+    }
+
+    // sentencepiece only supports removal of leading, trailing, and
+    // duplicate whitespace as a whole.
+    if strip != collapse_whitespace {
+        return Err(unsupported(
+            "whitespace normalization that only strips or only collapses whitespace",
+        ));
+    }
+
+    Ok(NormalizerSpec {
+        name: Some(
+            if precompiled_charsmap.is_some() {
+                "user_defined"
+            } else {
+                "identity"
+            }
+            .to_string(),
+        ),
+        precompiled_charsmap,
+        remove_extra_whitespaces: Some(strip),
+        escape_whitespaces: Some(true),
+        ..Default::default()
+    })
+}
+
+/// Get the sentencepiece `add_dummy_prefix` setting of a pre-tokenizer.
+fn add_dummy_prefix(pre_tokenizer: &Value) -> Result<bool, SentencePieceError> {
+    let metaspace = match component_type(pre_tokenizer) {
+        "Metaspace" => pre_tokenizer,
+        "Sequence" => match pre_tokenizer["pretokenizers"].as_array().map(Vec::as_slice) {
+            // Splitting on whitespace is redundant, since sentencepiece
+            // pieces do not cross whitespace.
+            Some([split, metaspace])
+                if component_type(split) == "WhitespaceSplit"
+                    && component_type(metaspace) == "Metaspace" =>
+            {
+                metaspace
+            }
+            _ => return Err(unsupported("pre-tokenizer sequence")),
+        },
+        _ if pre_tokenizer.is_null() => {
+            return Err(unsupported("tokenizer without Metaspace pre-tokenizer"))
+        }
+        pre_tokenizer_type => {
+            return Err(unsupported(format!("pre-tokenizer {}", pre_tokenizer_type)))
+        }
+    };
+
+    if metaspace["replacement"] != META_SYMBOL {
+        return Err(unsupported(format!(
+            "Metaspace replacement {}",
+            metaspace["replacement"]
+        )));
+    }
+
+    match (
+        metaspace["prepend_scheme"].as_str(),
+        metaspace["add_prefix_space"].as_bool(),
+    ) {
+        (Some("always"), _) | (Some("first"), _) => Ok(true),
+        (Some("never"), _) => Ok(false),
+        (Some(scheme), _) => Err(unsupported(format!("Metaspace prepend scheme {}", scheme))),
+        (None, add_prefix_space) => Ok(add_prefix_space.unwrap_or(true)),
+    }
+}
@@ -0,0 +1,50 @@
+use crate::ModelType;
+
+/// Summary of a sentencepiece model.
+///
+/// The summary can be used to verify that the expected model was loaded.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModelInfo {
+    /// The type of the model.
+    pub model_type: ModelType,
+
+    /// The number of pieces in the vocabulary.
+    pub vocab_size: usize,
+
+    /// Identifier of the begin of sentence piece.
+    pub bos_id: Option<u32>,
+
+    /// Identifier of the end of sentence piece.
+    pub eos_id: Option<u32>,
+
+    /// Identifier of the padding piece.
+    pub pad_id: Option<u32>,
+
+    /// Identifier of the unknown piece.
+    pub unk_id: u32,
+
+    /// Name of the normalization rule.
+    pub normalizer_name: Option<String>,
+
+    /// Whether unknown pieces are decomposed into byte pieces.
+    pub byte_fallback: bool,
+
+    /// Fingerprint of the serialized model.
+    ///
+    /// This is the 64-bit FNV-1a hash of the model protobuf as it is
+    /// serialized by sentencepiece after loading, so models that only
+    /// differ in their serialization have the same fingerprint. It is not
+    /// a cryptographic hash, but stable across platforms and versions of
+    /// this crate. Vocabulary restrictions do not change the fingerprint.
+    pub fingerprint: u64,
+}
+
+/// Compute the 64-bit FNV-1a hash of the given data.
+pub(crate) fn fingerprint(data: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    data.iter().fold(FNV_OFFSET_BASIS, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
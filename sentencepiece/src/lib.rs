@@ -15,7 +15,10 @@
 //!   "▁a", "▁t", "el", "es", "c", "o", "pe", "."]);
 //! ```
 
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::ffi::{c_void, CString, NulError};
+use std::fmt;
 use std::ops::{Deref, Drop};
 use std::os::raw::c_char;
 use std::path::{Path, PathBuf};
@@ -26,14 +29,53 @@ use num_traits::{FromPrimitive, Signed};
 use thiserror::Error;
 
 use sentencepiece_sys::{
-    spp_bos_id, spp_decode_piece_ids, spp_decode_pieces, spp_encode_as_serialized_proto,
-    spp_eos_id, spp_free, spp_from_serialized_proto, spp_is_unknown, spp_load, spp_new, spp_pad_id,
-    spp_piece_size, spp_piece_to_id, spp_sample_encode_as_serialized_proto,
-    spp_to_serialized_proto, spp_unk_id, SentencePieceProcessor as CSentencePieceProcessor,
+    spp_bos_id, spp_decode_piece_ids, spp_decode_piece_ids_as_serialized_proto, spp_decode_pieces,
+    spp_decode_pieces_as_serialized_proto, spp_encode_as_ids, spp_encode_as_pieces,
+    spp_encode_as_serialized_proto, spp_eos_id, spp_free, spp_from_serialized_proto, spp_get_score,
+    spp_id_to_piece, spp_is_byte, spp_is_control, spp_is_unknown, spp_is_unused, spp_load,
+    spp_load_vocabulary, spp_nbest_encode_as_serialized_proto, spp_new, spp_normalize, spp_pad_id,
+    spp_piece_size, spp_piece_to_id, spp_reset_vocabulary,
+    spp_sample_encode_and_score_as_serialized_proto, spp_sample_encode_as_serialized_proto,
+    spp_set_decode_extra_options, spp_set_encode_extra_options, spp_set_random_generator_seed,
+    spp_set_vocabulary, spp_to_serialized_proto, spp_unk_id,
+    SentencePieceProcessor as CSentencePieceProcessor,
 };
+#[cfg(all(feature = "static", not(feature = "system")))]
+use sentencepiece_sys::{spp_restore_random_generator, spp_seed_random_generator};
 
 mod sentencepiece;
-use crate::sentencepiece::SentencePieceText;
+use crate::sentencepiece::{NBestSentencePieceText, SentencePiece, SentencePieceText};
+
+pub mod sentencepiece_model;
+use crate::sentencepiece_model::ModelProto;
+
+mod editor;
+pub use crate::editor::ModelEditor;
+
+pub mod gguf;
+
+pub mod huggingface;
+
+mod info;
+pub use crate::info::ModelInfo;
+
+pub mod lint;
+use crate::lint::LintIssue;
+
+mod logging;
+pub use crate::logging::{set_log_output, set_min_log_level, LogLevel, LogOutput};
+
+mod normalization_rules;
+pub use crate::normalization_rules::NormalizationRules;
+
+mod normalizer;
+pub use crate::normalizer::Normalizer;
+
+mod trainer;
+pub use crate::trainer::TrainerOptions;
+
+mod vocab;
+pub use crate::vocab::{Vocab, VocabEntry};
 
 /// Sentence piece with its identifier and string span.
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -44,10 +86,234 @@ pub struct PieceWithId {
     /// The vocabulary identifier of the sentence piece.
     pub id: u32,
 
+    /// The span of the sentence piece in the tokenized string.
+    ///
+    /// The span is encoded as the byte offsets *[begin, end)*. Pieces that
+    /// do not occur in the tokenized string, such as begin/end of sentence
+    /// markers added through [`EncodeOptions`], have an empty span
+    /// (*begin = end*).
+    pub span: (u32, u32),
+}
+
+/// Options that are applied when encoding a sentence.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EncodeOptions {
+    /// Add the begin of sentence piece.
+    pub add_bos: bool,
+
+    /// Add the end of sentence piece.
+    pub add_eos: bool,
+
+    /// Reverse the order of the pieces. Begin/end of sentence pieces are
+    /// added after reversal.
+    pub reverse: bool,
+
+    /// Use the unknown piece (e.g. `<unk>`) as the piece of unknown
+    /// text, rather than the unknown text itself.
+    pub unk_piece: bool,
+}
+
+impl EncodeOptions {
+    fn to_extra_options(&self) -> CString {
+        let mut options = Vec::new();
+        if self.reverse {
+            options.push("reverse");
+        }
+        if self.add_bos {
+            options.push("bos");
+        }
+        if self.add_eos {
+            options.push("eos");
+        }
+        if self.unk_piece {
+            options.push("unk");
+        }
+
+        CString::new(options.join(":")).expect("Extra options contain nul byte")
+    }
+}
+
+/// Options that are applied when decoding a sentence.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DecodeOptions {
+    /// Reverse the order of the pieces before decoding.
+    pub reverse: bool,
+
+    /// The surface of unknown pieces in the decoded sentence. If absent,
+    /// the surface from the model is used (usually ` ⁇ `).
+    pub unk_surface: Option<String>,
+}
+
+impl DecodeOptions {
+    fn to_extra_options(&self) -> CString {
+        let options = if self.reverse { "reverse" } else { "" };
+        CString::new(options).expect("Extra options contain nul byte")
+    }
+}
+
+/// Type of a sentence piece.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum PieceType {
+    /// Normal piece.
+    Normal = 1,
+
+    /// Unknown piece.
+    Unknown = 2,
+
+    /// Control piece, such as the begin/end of sentence markers.
+    Control = 3,
+
+    /// User-defined piece, which is always segmented as a single piece.
+    UserDefined = 4,
+
+    /// Unused piece, which is never produced by segmentation.
+    Unused = 5,
+
+    /// Byte piece, used for byte fallback.
+    Byte = 6,
+}
+
+/// Type of a sentencepiece model.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum ModelType {
+    /// Unigram language model.
+    #[default]
+    Unigram = 1,
+
+    /// Byte pair encoding.
+    Bpe = 2,
+
+    /// Whitespace-delimited words.
+    Word = 3,
+
+    /// Characters.
+    Char = 4,
+}
+
+impl PieceType {
+    fn from_proto(piece_type: i32) -> Option<Self> {
+        match piece_type {
+            1 => Some(PieceType::Normal),
+            2 => Some(PieceType::Unknown),
+            3 => Some(PieceType::Control),
+            4 => Some(PieceType::UserDefined),
+            5 => Some(PieceType::Unused),
+            6 => Some(PieceType::Byte),
+            _ => None,
+        }
+    }
+}
+
+impl ModelType {
+    fn from_proto(model_type: i32) -> Option<Self> {
+        match model_type {
+            1 => Some(ModelType::Unigram),
+            2 => Some(ModelType::Bpe),
+            3 => Some(ModelType::Word),
+            4 => Some(ModelType::Char),
+            _ => None,
+        }
+    }
+}
+
+/// Configuration for sampling segmentations (subword regularization).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SamplingConfig {
+    /// Sample segmentations from the lattice of a unigram model.
+    Unigram {
+        /// Sample from the `n_best` best segmentations, or from all
+        /// segmentations when `None`. At most 512 segmentations can be
+        /// used.
+        n_best: Option<usize>,
+
+        /// Smoothing parameter for the distribution, must be a (normal)
+        /// positive floating point number.
+        alpha: f32,
+    },
+
+    /// BPE-dropout, for byte pair encoding models.
+    BpeDropout {
+        /// Probability of dropping a merge, must be in *[0, 1]*.
+        dropout: f32,
+    },
+}
+
+impl SamplingConfig {
+    fn validate(&self, model_type: ModelType) -> Result<(), SentencePieceError> {
+        match *self {
+            SamplingConfig::Unigram { n_best, alpha } => {
+                if model_type != ModelType::Unigram {
+                    return Err(SentencePieceError::UnsupportedSampling(model_type));
+                }
+                if let Some(n_best) = n_best {
+                    if n_best == 0 || n_best > 512 {
+                        return Err(SentencePieceError::InvalidNBest(n_best));
+                    }
+                }
+                if !(alpha.is_normal() && alpha.is_positive()) {
+                    return Err(SentencePieceError::InvalidSamplingAlpha);
+                }
+            }
+            SamplingConfig::BpeDropout { dropout } => {
+                if model_type != ModelType::Bpe {
+                    return Err(SentencePieceError::UnsupportedSampling(model_type));
+                }
+                if !(0.0..=1.0).contains(&dropout) {
+                    return Err(SentencePieceError::InvalidDropout);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sentence piece with all the information provided by sentencepiece.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EncodedPiece {
+    /// The sentence piece as a string.
+    pub piece: String,
+
+    /// The vocabulary identifier of the sentence piece.
+    pub id: u32,
+
+    /// The surface form of the sentence piece in the tokenized string.
+    pub surface: String,
+
     /// The span of the sentence piece in the tokenized string.
     ///
     /// The span is encoded as the byte offsets *[begin, end)*.
     pub span: (u32, u32),
+
+    /// The type of the sentence piece.
+    pub piece_type: PieceType,
+}
+
+/// Encoded sentence with all the information provided by sentencepiece.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncodedText {
+    /// The normalized sentence.
+    pub normalized: String,
+
+    /// The sentence pieces.
+    pub pieces: Vec<EncodedPiece>,
+
+    /// The score of the segmentation (usually the log probability).
+    ///
+    /// sentencepiece does not provide a score for every model type. Use
+    /// `nbest_encode` with `n = 1` to get the score of the best
+    /// segmentation of a unigram model.
+    pub score: Option<f32>,
+}
+
+/// Segmentation of a sentence with its score.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segmentation {
+    /// The sentence pieces of the segmentation.
+    pub pieces: Vec<PieceWithId>,
+
+    /// The score of the segmentation (usually the log probability).
+    pub score: f32,
 }
 
 #[derive(Clone, Debug, Eq, Error, PartialEq)]
@@ -56,6 +322,12 @@ pub enum SentencePieceError {
     #[error("sentencepiece error: {0}")]
     CError(CSentencePieceError),
 
+    #[error("Model already contains piece: {0}")]
+    DuplicatePiece(String),
+
+    #[error("Inconsistent tokenizer metadata: {0}")]
+    InconsistentMetadata(String),
+
     #[error("sentencepiece could not encode the text")]
     EncodeError,
 
@@ -65,11 +337,74 @@ pub enum SentencePieceError {
     #[error("Filename is not valid UTF-8: {0}")]
     FilenameNotUtf8(PathBuf),
 
+    #[error("Piece identifier is out of range: {0}")]
+    IdOutOfRange(u32),
+
+    #[error("Invalid precompiled normalization rules: {0}")]
+    InvalidCharsMap(String),
+
+    #[error("Invalid GGUF file: {0}")]
+    InvalidGguf(String),
+
+    #[error("BPE dropout probability must be in [0, 1]")]
+    InvalidDropout,
+
+    #[error("Number of best segmentations to sample from must be in [1, 512], was: {0}")]
+    InvalidNBest(usize),
+
+    #[error("The best segmentation can only be included when sampling without replacement")]
+    InvalidIncludeBest,
+
+    #[error("Number of samples must be in [1, 2147483647], was: {0}")]
+    InvalidNumSamples(usize),
+
+    #[error("Invalid model JSON: {0}")]
+    InvalidModelJson(String),
+
+    #[error("Invalid model protobuf: {0}")]
+    InvalidModelProto(String),
+
+    #[error("Normalization rules cannot be compiled: {0}")]
+    InvalidNormalizationRules(String),
+
+    #[error("Sampling alpha is out of range for the sampling method")]
+    InvalidSamplingAlpha,
+
+    #[error("Piece score must be finite")]
+    InvalidScore,
+
+    #[error("Invalid tokenizer JSON: {0}")]
+    InvalidTokenizerJson(String),
+
+    #[error("Invalid trainer options: {0}")]
+    InvalidTrainerOptions(String),
+
     #[error("Encoded text did not contain {0}")]
     MissingData(String),
 
+    #[error("Tokenizer metadata is missing: {0}")]
+    MissingMetadata(String),
+
     #[error("Piece contains nul byte")]
     PieceContainsNul,
+
+    #[error("Model does not contain piece: {0}")]
+    PieceNotFound(String),
+
+    #[error("sentencepiece could not train the model: {0}: {1}")]
+    TrainError(CSentencePieceError, String),
+
+    #[error("Model must contain exactly one unknown piece, found: {0}")]
+    UnknownPieceCount(usize),
+
+    #[error("Conversion is not supported for {0:?} models")]
+    UnsupportedConversion(ModelType),
+
+    #[error("Sampling configuration is not supported by {0:?} models")]
+    UnsupportedSampling(ModelType),
+
+    #[error("Tokenizer cannot be converted to a sentencepiece model, unsupported: {0}")]
+    UnsupportedTokenizer(String),
 }
 
 /// Errors that returned by the `sentencepiece` library.
@@ -111,32 +446,197 @@ pub enum CSentencePieceError {
 }
 
 /// Small wrapper struct to deallocate data automatically.
-struct CData {
-    data: *const u8,
+struct CData<T = u8> {
+    data: *const T,
     len: usize,
 }
 
-impl Deref for CData {
-    type Target = [u8];
+impl<T> Deref for CData<T> {
+    type Target = [T];
 
     fn deref(&self) -> &Self::Target {
+        // malloc is allowed to return a null pointer for empty allocations.
+        if self.len == 0 {
+            return &[];
+        }
+
         unsafe { slice::from_raw_parts(self.data, self.len) }
     }
 }
 
-impl Drop for CData {
+impl<T> Drop for CData<T> {
     fn drop(&mut self) {
         unsafe { libc::free(self.data as *mut c_void) }
     }
 }
 
+/// Convert a status code returned by the `sentencepiece` library.
+fn check_status(status: i32) -> Result<(), SentencePieceError> {
+    if status == 0 {
+        Ok(())
+    } else {
+        let c_error = match FromPrimitive::from_i32(status) {
+            Some(error) => error,
+            None => unreachable!(),
+        };
+        Err(SentencePieceError::CError(c_error))
+    }
+}
+
+/// Convert a path to a C string for the `sentencepiece` library.
+fn path_to_c_string(path: &Path) -> Result<CString, SentencePieceError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| SentencePieceError::FilenameContainsNul(path.to_owned()))
+    }
+    #[cfg(not(unix))]
+    {
+        CString::new(
+            path.to_str()
+                .ok_or(SentencePieceError::FilenameNotUtf8(path.to_owned()))?,
+        )
+        .map_err(|_| SentencePieceError::FilenameContainsNul(path.to_owned()))
+    }
+}
+
+/// Set the seed of the random generators used for sampling.
+///
+/// sentencepiece uses a random generator per thread, which is seeded when
+/// the thread samples for the first time. Consequently, the seed only
+/// affects threads that have not used sampling yet. Since the seed applies
+/// to the whole process and cannot be reset, prefer
+/// `with_random_generator_seed` when it is available, which makes
+/// sampling reproducible regardless of earlier sampling.
+///
+/// By default, random generators are seeded using `std::random_device`.
+/// sentencepiece ignores the seed `u32::MAX`, which it reserves for this
+/// default.
+pub fn set_random_generator_seed(seed: u32) {
+    unsafe { spp_set_random_generator_seed(seed) }
+}
+
+/// Call a function with the random generator of the calling thread seeded
+/// with `seed`.
+///
+/// The state of the random generator is restored afterwards, so that the
+/// seed does not affect later sampling. Calls with the same seed and
+/// function thus result in the same samples. Only sampling in the calling
+/// thread uses the seeded generator, sampling in other threads (including
+/// threads spawned by `f`) is not affected.
+///
+/// This function is only available with the `static` feature. The random
+/// generator of a thread is not part of the public sentencepiece API, so
+/// it can only be accessed when sentencepiece is built from the bundled
+/// source. Use [`set_random_generator_seed`] with a system library.
+///
+/// ```
+/// use sentencepiece::{with_random_generator_seed, SamplingConfig, SentencePieceProcessor};
+///
+/// let spp = SentencePieceProcessor::open("testdata/toy.model").unwrap();
+/// let config = SamplingConfig::Unigram { n_best: None, alpha: 0.1 };
+/// let sample = |seed| with_random_generator_seed(seed, || {
+///   spp.sample_encode("I saw a girl with a telescope.", config).unwrap()
+/// });
+/// assert_eq!(sample(42), sample(42));
+/// ```
+#[cfg(all(feature = "static", not(feature = "system")))]
+pub fn with_random_generator_seed<F, T>(seed: u32, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    /// Restores the random generator, also when `f` panics.
+    struct SavedGenerator(*mut c_void);
+
+    impl Drop for SavedGenerator {
+        fn drop(&mut self) {
+            unsafe { spp_restore_random_generator(self.0) }
+        }
+    }
+
+    let _saved = SavedGenerator(unsafe { spp_seed_random_generator(seed) });
+
+    f()
+}
+
+/// Model data that is not exposed by the `sentencepiece` API.
+#[derive(Debug, Default)]
+struct ModelMetadata {
+    model_type: ModelType,
+    unk_piece: String,
+    user_defined: HashSet<u32>,
+    normalizer_name: Option<String>,
+    byte_fallback: bool,
+    fingerprint: u64,
+}
+
+impl ModelMetadata {
+    /// Decode the metadata of a model that was loaded by sentencepiece.
+    ///
+    /// Decoding can fail for models that sentencepiece accepts, since
+    /// sentencepiece does not validate that string fields are UTF-8.
+    fn from_serialized_proto(data: &[u8]) -> Result<Self, SentencePieceError> {
+        let model: ModelProto = prost::Message::decode(data)
+            .map_err(|err| SentencePieceError::InvalidModelProto(err.to_string()))?;
+
+        let user_defined = model
+            .pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, piece)| piece.r#type == Some(PieceType::UserDefined as i32))
+            .map(|(id, _)| id as u32)
+            .collect();
+
+        let unk_piece = model
+            .pieces
+            .iter()
+            .find(|piece| piece.r#type == Some(PieceType::Unknown as i32))
+            .and_then(|piece| piece.piece.clone())
+            .unwrap_or_default();
+
+        // sentencepiece treats unknown model types as unset, so they are
+        // loaded as unigram models.
+        let model_type = model
+            .trainer_spec
+            .as_ref()
+            .and_then(|spec| spec.model_type)
+            .and_then(ModelType::from_proto)
+            .unwrap_or_default();
+
+        Ok(ModelMetadata {
+            model_type,
+            unk_piece,
+            user_defined,
+            normalizer_name: model.normalizer_spec.and_then(|spec| spec.name),
+            byte_fallback: model
+                .trainer_spec
+                .map(|spec| spec.byte_fallback())
+                .unwrap_or_default(),
+            fingerprint: info::fingerprint(data),
+        })
+    }
+}
+
 /// Sentence piece tokenizer.
 ///
 /// Instances of `SentencePieceProcessor` can be used to tokenizer a
 /// sentence using a sentencepiece model.
-#[derive(Debug)]
 pub struct SentencePieceProcessor {
     inner: *mut CSentencePieceProcessor,
+    metadata: ModelMetadata,
+    encode_options: EncodeOptions,
+    decode_options: DecodeOptions,
+}
+
+impl fmt::Debug for SentencePieceProcessor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SentencePieceProcessor")
+            .field("info", &self.info())
+            .field("encode_options", &self.encode_options)
+            .field("decode_options", &self.decode_options)
+            .finish()
+    }
 }
 
 impl Drop for SentencePieceProcessor {
@@ -147,23 +647,38 @@ impl Drop for SentencePieceProcessor {
 
 impl SentencePieceProcessor {
     pub fn from_serialized_proto(data: &[u8]) -> Result<Self, SentencePieceError> {
-        let spp = SentencePieceProcessor {
+        let mut spp = SentencePieceProcessor {
             inner: unsafe { spp_new() },
+            metadata: ModelMetadata::default(),
+            encode_options: EncodeOptions::default(),
+            decode_options: DecodeOptions::default(),
         };
 
         let result = unsafe {
             spp_from_serialized_proto(spp.inner, data.as_ptr() as *const c_char, data.len())
         };
 
-        if result == 0 {
-            Ok(spp)
-        } else {
-            let c_error = match FromPrimitive::from_i32(result) {
-                Some(error) => error,
-                None => unreachable!(),
-            };
-            Err(SentencePieceError::CError(c_error))
-        }
+        check_status(result)?;
+
+        // Use the canonical serialization, so that the fingerprint does
+        // not depend on how the caller serialized the model.
+        spp.metadata = ModelMetadata::from_serialized_proto(&spp.to_serialized_proto())?;
+
+        Ok(spp)
+    }
+
+    /// Load a model from its JSON serialization.
+    ///
+    /// ```
+    /// use sentencepiece::SentencePieceProcessor;
+    ///
+    /// let spp = SentencePieceProcessor::open("testdata/toy.model").unwrap();
+    /// let json = spp.model_proto().to_json();
+    /// let spp = SentencePieceProcessor::from_json(&json).unwrap();
+    /// ```
+    pub fn from_json(json: &str) -> Result<Self, SentencePieceError> {
+        let model = ModelProto::from_json(json)?;
+        Self::from_serialized_proto(&prost::Message::encode_to_vec(&model))
     }
 
     /// Serialize the model to protobuf.
@@ -178,34 +693,32 @@ impl SentencePieceProcessor {
 
     /// Open a sentencepiece model.
     pub fn open(path: impl AsRef<Path>) -> Result<Self, SentencePieceError> {
-        let spp = SentencePieceProcessor {
+        let mut spp = SentencePieceProcessor {
             inner: unsafe { spp_new() },
+            metadata: ModelMetadata::default(),
+            encode_options: EncodeOptions::default(),
+            decode_options: DecodeOptions::default(),
         };
 
-        let result;
-        #[cfg(unix)]
-        {
-            use std::os::unix::ffi::OsStrExt;
-            let c_filename = CString::new(path.as_ref().as_os_str().as_bytes())
-                .map_err(|_| SentencePieceError::FilenameContainsNul(path.as_ref().to_owned()))?;
-            result = unsafe { spp_load(spp.inner, c_filename.as_ptr()) };
-        }
-        #[cfg(not(unix))]
-        {
-            let c_filename = CString::new(path.as_ref().to_str().ok_or(
-                SentencePieceError::FilenameNotUtf8(path.as_ref().to_owned()),
-            )?)
-            .map_err(|_| SentencePieceError::FilenameContainsNul(path.as_ref().to_owned()))?;
-            result = unsafe { spp_load(spp.inner, c_filename.as_ptr()) };
-        }
-        if result == 0 {
-            Ok(spp)
+        let c_filename = path_to_c_string(path.as_ref())?;
+        let result = unsafe { spp_load(spp.inner, c_filename.as_ptr()) };
+
+        check_status(result)?;
+
+        spp.metadata = ModelMetadata::from_serialized_proto(&spp.to_serialized_proto())?;
+
+        Ok(spp)
+    }
+
+    /// Check that a piece identifier is in the vocabulary.
+    ///
+    /// The `sentencepiece` library asserts on identifiers that are out
+    /// of range, so they have to be checked before calling into it.
+    fn check_id(&self, id: u32) -> Result<(), SentencePieceError> {
+        if (id as usize) < self.len() {
+            Ok(())
         } else {
-            let c_error = match FromPrimitive::from_i32(result) {
-                Some(error) => error,
-                None => unreachable!(),
-            };
-            Err(SentencePieceError::CError(c_error))
+            Err(SentencePieceError::IdOutOfRange(id))
         }
     }
 
@@ -218,8 +731,37 @@ impl SentencePieceProcessor {
         }
     }
 
+    /// Get the options that are applied when decoding a sentence.
+    pub fn decode_options(&self) -> &DecodeOptions {
+        &self.decode_options
+    }
+
     /// Decode a sentence from piece identifiers.
     pub fn decode_piece_ids(&self, pieces: &[u32]) -> Result<String, SentencePieceError> {
+        if let Some(unk_surface) = &self.decode_options.unk_surface {
+            let mut proto = std::ptr::null_mut::<u8>();
+            let mut proto_len = 0;
+
+            let status = unsafe {
+                spp_decode_piece_ids_as_serialized_proto(
+                    self.inner,
+                    pieces.as_ptr(),
+                    pieces.len(),
+                    &mut proto,
+                    &mut proto_len,
+                )
+            };
+
+            let c_proto = CData {
+                data: proto,
+                len: proto_len,
+            };
+
+            check_status(status)?;
+
+            return Ok(self.decode_with_unk_surface(&c_proto, unk_surface));
+        }
+
         let mut decoded = std::ptr::null_mut::<u8>();
         let mut decoded_len = 0;
 
@@ -238,24 +780,15 @@ impl SentencePieceProcessor {
             len: decoded_len,
         };
 
-        if status == 0 {
-            let decoded_string = String::from_utf8(c_str.to_owned())
-                .expect("Decoded sentence is not UTF-8, please report this bug.");
+        check_status(status)?;
 
-            Ok(decoded_string)
-        } else {
-            let c_error = match FromPrimitive::from_i32(status) {
-                Some(error) => error,
-                None => unreachable!(),
-            };
-            Err(SentencePieceError::CError(c_error))
-        }
+        let decoded_string = String::from_utf8(c_str.to_owned())
+            .expect("Decoded sentence is not UTF-8, please report this bug.");
+
+        Ok(decoded_string)
     }
 
     pub fn decode_pieces(&self, pieces: &[impl AsRef<str>]) -> Result<String, SentencePieceError> {
-        let mut decoded = std::ptr::null_mut::<u8>();
-        let mut decoded_len = 0;
-
         let owned_c_pieces = pieces
             .iter()
             .map(|piece| CString::new(piece.as_ref()))
@@ -266,6 +799,33 @@ impl SentencePieceProcessor {
             .map(|piece| piece.as_ptr())
             .collect::<Vec<_>>();
 
+        if let Some(unk_surface) = &self.decode_options.unk_surface {
+            let mut proto = std::ptr::null_mut::<u8>();
+            let mut proto_len = 0;
+
+            let status = unsafe {
+                spp_decode_pieces_as_serialized_proto(
+                    self.inner,
+                    c_pieces.as_ptr(),
+                    c_pieces.len(),
+                    &mut proto,
+                    &mut proto_len,
+                )
+            };
+
+            let c_proto = CData {
+                data: proto,
+                len: proto_len,
+            };
+
+            check_status(status)?;
+
+            return Ok(self.decode_with_unk_surface(&c_proto, unk_surface));
+        }
+
+        let mut decoded = std::ptr::null_mut::<u8>();
+        let mut decoded_len = 0;
+
         let status = unsafe {
             spp_decode_pieces(
                 self.inner,
@@ -281,18 +841,43 @@ impl SentencePieceProcessor {
             len: decoded_len,
         };
 
-        if status == 0 {
-            let decoded_string = String::from_utf8(c_str.to_owned())
-                .expect("Decoded sentence is not UTF-8, please report this bug.");
+        check_status(status)?;
 
-            Ok(decoded_string)
-        } else {
-            let c_error = match FromPrimitive::from_i32(status) {
-                Some(error) => error,
-                None => unreachable!(),
-            };
-            Err(SentencePieceError::CError(c_error))
-        }
+        let decoded_string = String::from_utf8(c_str.to_owned())
+            .expect("Decoded sentence is not UTF-8, please report this bug.");
+
+        Ok(decoded_string)
+    }
+
+    /// Construct a decoded sentence, replacing the surface of unknown pieces.
+    fn decode_with_unk_surface(&self, c_proto: &CData, unk_surface: &str) -> String {
+        let sp_text: SentencePieceText = prost::Message::decode(&**c_proto)
+            .expect("Received invalid protobuf from sentencepiece");
+
+        // The decoded sentence is the concatenation of the piece surfaces.
+        // Unknown text that is not decoded from the unknown piece retains
+        // its own surface.
+        sp_text
+            .pieces
+            .into_iter()
+            .map(|piece| {
+                let is_unk_piece = piece.piece.as_deref() == Some(&self.metadata.unk_piece)
+                    && piece
+                        .id
+                        .map(|id| unsafe { spp_is_unknown(self.inner, id as i32) })
+                        .unwrap_or(false);
+                if is_unk_piece {
+                    unk_surface.to_string()
+                } else {
+                    piece.surface.unwrap_or_default()
+                }
+            })
+            .collect()
+    }
+
+    /// Get the options that are applied when encoding a sentence.
+    pub fn encode_options(&self) -> &EncodeOptions {
+        &self.encode_options
     }
 
     /// Encode a sentence as sentence pieces and their identifiers.
@@ -310,34 +895,299 @@ impl SentencePieceProcessor {
         Self::process_encode_protobuf(CData { data: c_proto, len })
     }
 
-    pub fn eos_id(&self) -> Option<u32> {
-        let eos_id = unsafe { spp_eos_id(self.inner) };
-        if eos_id < 0 {
-            None
-        } else {
-            Some(eos_id as u32)
+    /// Encode a sentence with all the information provided by sentencepiece.
+    ///
+    /// In contrast to [`encode`](Self::encode), this also returns the
+    /// normalized sentence, the surface form and type of each piece, and
+    /// the score of the segmentation.
+    pub fn encode_full(&self, sentence: &str) -> Result<EncodedText, SentencePieceError> {
+        let mut len = 0usize;
+        let c_proto = unsafe {
+            spp_encode_as_serialized_proto(
+                self.inner,
+                sentence.as_ptr() as *const c_char,
+                sentence.len(),
+                &mut len,
+            )
+        };
+        let c_proto = CData { data: c_proto, len };
+
+        // Errors are communicated as empty data.
+        if c_proto.is_empty() {
+            return Err(SentencePieceError::EncodeError);
         }
-    }
 
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
-    }
+        let sp_text: SentencePieceText = prost::Message::decode(&*c_proto)
+            .expect("Received invalid protobuf from sentencepiece");
 
-    pub fn len(&self) -> usize {
-        let len = unsafe { spp_piece_size(self.inner) };
-        assert!(len >= 0);
-        len as usize
+        let pieces = sp_text
+            .pieces
+            .into_iter()
+            .map(|proto_piece| {
+                let id = proto_piece
+                    .id
+                    .ok_or_else(|| SentencePieceError::MissingData("id".to_string()))?;
+                Ok(EncodedPiece {
+                    piece: proto_piece
+                        .piece
+                        .ok_or_else(|| SentencePieceError::MissingData("piece".to_string()))?,
+                    id,
+                    surface: proto_piece
+                        .surface
+                        .ok_or_else(|| SentencePieceError::MissingData("surface".to_string()))?,
+                    span: (
+                        proto_piece
+                            .begin
+                            .ok_or_else(|| SentencePieceError::MissingData("begin".to_string()))?,
+                        proto_piece
+                            .end
+                            .ok_or_else(|| SentencePieceError::MissingData("end".to_string()))?,
+                    ),
+                    piece_type: self.piece_type_unchecked(id),
+                })
+            })
+            .collect::<Result<_, SentencePieceError>>()?;
+
+        // The normalized sentence cannot be reconstructed from the pieces,
+        // since unknown pieces do not retain their normalized text.
+        let (normalized, _) = self.normalize_with_alignment(sentence)?;
+
+        Ok(EncodedText {
+            normalized,
+            pieces,
+            score: sp_text.score,
+        })
     }
 
-    pub fn pad_id(&self) -> Option<u32> {
-        let pad_id = unsafe { spp_pad_id(self.inner) };
-        if pad_id < 0 {
-            None
+    /// Encode a sentence as piece identifiers.
+    ///
+    /// This is a faster alternative to [`encode`](Self::encode) when only
+    /// the identifiers of the pieces are needed, since it avoids the
+    /// protobuf round trip.
+    pub fn encode_ids(&self, sentence: &str) -> Result<Vec<u32>, SentencePieceError> {
+        let mut ids = std::ptr::null_mut::<u32>();
+        let mut ids_len = 0;
+
+        let status = unsafe {
+            spp_encode_as_ids(
+                self.inner,
+                sentence.as_ptr() as *const c_char,
+                sentence.len(),
+                &mut ids,
+                &mut ids_len,
+            )
+        };
+
+        let c_ids = CData {
+            data: ids,
+            len: ids_len,
+        };
+
+        check_status(status)?;
+
+        Ok(c_ids.to_vec())
+    }
+
+    /// Encode a sentence as sentence pieces.
+    ///
+    /// This is a faster alternative to [`encode`](Self::encode) when only
+    /// the pieces are needed, since it avoids the protobuf round trip.
+    pub fn encode_pieces(&self, sentence: &str) -> Result<Vec<String>, SentencePieceError> {
+        let mut pieces = std::ptr::null_mut::<u8>();
+        let mut pieces_len = 0;
+        let mut piece_lens = std::ptr::null_mut::<usize>();
+        let mut piece_lens_len = 0;
+
+        let status = unsafe {
+            spp_encode_as_pieces(
+                self.inner,
+                sentence.as_ptr() as *const c_char,
+                sentence.len(),
+                &mut pieces,
+                &mut pieces_len,
+                &mut piece_lens,
+                &mut piece_lens_len,
+            )
+        };
+
+        let c_pieces = CData {
+            data: pieces,
+            len: pieces_len,
+        };
+        let c_piece_lens = CData {
+            data: piece_lens,
+            len: piece_lens_len,
+        };
+
+        check_status(status)?;
+
+        let mut offset = 0;
+        let pieces = c_piece_lens
+            .iter()
+            .map(|&piece_len| {
+                let piece = &c_pieces[offset..offset + piece_len];
+                offset += piece_len;
+                String::from_utf8(piece.to_owned())
+                    .expect("Piece is not UTF-8, please report this bug.")
+            })
+            .collect();
+
+        Ok(pieces)
+    }
+
+    pub fn eos_id(&self) -> Option<u32> {
+        let eos_id = unsafe { spp_eos_id(self.inner) };
+        if eos_id < 0 {
+            None
+        } else {
+            Some(eos_id as u32)
+        }
+    }
+
+    /// Encode a sentence as its `n` best segmentations.
+    ///
+    /// The segmentations are ordered from best to worst, each with its
+    /// score. This is only supported by unigram models.
+    pub fn nbest_encode(
+        &self,
+        sentence: &str,
+        n: usize,
+    ) -> Result<Vec<Segmentation>, SentencePieceError> {
+        let mut len = 0usize;
+        let c_proto = unsafe {
+            spp_nbest_encode_as_serialized_proto(
+                self.inner,
+                sentence.as_ptr() as *const c_char,
+                sentence.len(),
+                &mut len,
+                n,
+            )
+        };
+
+        Self::process_nbest_encode_protobuf(CData { data: c_proto, len })
+    }
+
+    /// Get the piece with the given identifier.
+    pub fn id_to_piece(&self, id: u32) -> Result<String, SentencePieceError> {
+        self.check_id(id)?;
+
+        let mut len = 0;
+        let data = unsafe { spp_id_to_piece(self.inner, id as i32, &mut len) };
+        let c_piece = CData { data, len };
+
+        Ok(String::from_utf8(c_piece.to_owned())
+            .expect("Piece is not UTF-8, please report this bug."))
+    }
+
+    /// Get a summary of the model.
+    ///
+    /// The parts of the summary that are not available through the
+    /// `sentencepiece` API are computed when the model is loaded.
+    pub fn info(&self) -> ModelInfo {
+        ModelInfo {
+            model_type: self.model_type(),
+            vocab_size: self.len(),
+            bos_id: self.bos_id(),
+            eos_id: self.eos_id(),
+            pad_id: self.pad_id(),
+            unk_id: self.unk_id(),
+            normalizer_name: self.metadata.normalizer_name.clone(),
+            byte_fallback: self.metadata.byte_fallback,
+            fingerprint: self.metadata.fingerprint,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn len(&self) -> usize {
+        let len = unsafe { spp_piece_size(self.inner) };
+        assert!(len >= 0);
+        len as usize
+    }
+
+    pub fn pad_id(&self) -> Option<u32> {
+        let pad_id = unsafe { spp_pad_id(self.inner) };
+        if pad_id < 0 {
+            None
         } else {
             Some(pad_id as u32)
         }
     }
 
+    fn normalize_with_alignment(
+        &self,
+        sentence: &str,
+    ) -> Result<(String, Vec<usize>), SentencePieceError> {
+        let mut normalized = std::ptr::null_mut::<u8>();
+        let mut normalized_len = 0;
+        let mut norm_to_orig = std::ptr::null_mut::<usize>();
+        let mut norm_to_orig_len = 0;
+
+        let status = unsafe {
+            spp_normalize(
+                self.inner,
+                sentence.as_ptr() as *const c_char,
+                sentence.len(),
+                &mut normalized,
+                &mut normalized_len,
+                &mut norm_to_orig,
+                &mut norm_to_orig_len,
+            )
+        };
+
+        let c_normalized = CData {
+            data: normalized,
+            len: normalized_len,
+        };
+        let c_norm_to_orig = CData {
+            data: norm_to_orig,
+            len: norm_to_orig_len,
+        };
+
+        check_status(status)?;
+
+        let normalized_string = String::from_utf8(c_normalized.to_owned())
+            .expect("Normalized sentence is not UTF-8, please report this bug.");
+
+        Ok((normalized_string, c_norm_to_orig.to_vec()))
+    }
+
+    /// Check the model for problems.
+    ///
+    /// See [`lint_model`](crate::lint::lint_model) for more information.
+    pub fn lint(&self) -> Vec<LintIssue> {
+        lint::lint_model(&self.model_proto())
+    }
+
+    /// Restrict segmentation to the vocabulary in a file.
+    ///
+    /// Each line of the file contains a piece, optionally followed by a tab
+    /// and the frequency of the piece. Only pieces with a frequency of at
+    /// least `threshold` are added to the vocabulary. See
+    /// [`set_vocabulary`](Self::set_vocabulary) for more information.
+    pub fn load_vocabulary(
+        &mut self,
+        path: impl AsRef<Path>,
+        threshold: i32,
+    ) -> Result<(), SentencePieceError> {
+        let c_filename = path_to_c_string(path.as_ref())?;
+        let status = unsafe { spp_load_vocabulary(self.inner, c_filename.as_ptr(), threshold) };
+        check_status(status)
+    }
+
+    /// Get the model as a protocol buffer message.
+    pub fn model_proto(&self) -> ModelProto {
+        prost::Message::decode(&*self.to_serialized_proto())
+            .expect("Received invalid model protobuf from sentencepiece")
+    }
+
+    /// Get the type of the model.
+    pub fn model_type(&self) -> ModelType {
+        self.metadata.model_type
+    }
+
     /// Get the identifier of a sentence piece.
     pub fn piece_to_id(&self, piece: &str) -> Result<Option<u32>, NulError> {
         let c_piece = CString::new(piece.as_bytes())?;
@@ -350,20 +1200,71 @@ impl SentencePieceProcessor {
         }
     }
 
+    /// Get the type of the piece with the given identifier.
+    pub fn piece_type(&self, id: u32) -> Result<PieceType, SentencePieceError> {
+        self.check_id(id)?;
+        Ok(self.piece_type_unchecked(id))
+    }
+
+    /// Get the type of a piece, the identifier must be in the vocabulary.
+    fn piece_type_unchecked(&self, id: u32) -> PieceType {
+        let c_id = id as i32;
+        if unsafe { spp_is_unknown(self.inner, c_id) } {
+            PieceType::Unknown
+        } else if unsafe { spp_is_control(self.inner, c_id) } {
+            PieceType::Control
+        } else if unsafe { spp_is_unused(self.inner, c_id) } {
+            PieceType::Unused
+        } else if unsafe { spp_is_byte(self.inner, c_id) } {
+            PieceType::Byte
+        } else if self.metadata.user_defined.contains(&id) {
+            PieceType::UserDefined
+        } else {
+            PieceType::Normal
+        }
+    }
+
     fn process_encode_protobuf(c_proto: CData) -> Result<Vec<PieceWithId>, SentencePieceError> {
         // Errors are communicated as empty data.
         if c_proto.len() == 0 {
             return Err(SentencePieceError::EncodeError);
         }
 
-        let proto: Vec<u8> = c_proto.to_owned();
-        let sp_text: SentencePieceText = prost::Message::decode(proto.as_slice())
+        let sp_text: SentencePieceText = prost::Message::decode(&*c_proto)
+            .expect("Received invalid protobuf from sentencepiece");
+
+        Self::convert_pieces(sp_text.pieces)
+    }
+
+    fn process_nbest_encode_protobuf(
+        c_proto: CData,
+    ) -> Result<Vec<Segmentation>, SentencePieceError> {
+        // Errors are communicated as empty data.
+        if c_proto.is_empty() {
+            return Err(SentencePieceError::EncodeError);
+        }
+
+        let nbest_text: NBestSentencePieceText = prost::Message::decode(&*c_proto)
             .expect("Received invalid protobuf from sentencepiece");
 
+        nbest_text
+            .nbests
+            .into_iter()
+            .map(|sp_text| {
+                Ok(Segmentation {
+                    score: sp_text
+                        .score
+                        .ok_or_else(|| SentencePieceError::MissingData("score".to_string()))?,
+                    pieces: Self::convert_pieces(sp_text.pieces)?,
+                })
+            })
+            .collect()
+    }
+
+    fn convert_pieces(pieces: Vec<SentencePiece>) -> Result<Vec<PieceWithId>, SentencePieceError> {
         // Most fields in the sentencepiece protobuf are optionals. Let's be
         // defensive about absent fields for a piece.
-        sp_text
-            .pieces
+        pieces
             .into_iter()
             .map(|proto_piece| {
                 Ok(PieceWithId {
@@ -388,26 +1289,32 @@ impl SentencePieceProcessor {
 
     /// Encode a sentence using sampling (subword regularization).
     ///
-    /// Sample for the `n_best` segmentations, where alpha controls the
-    /// smoothness of the distribution.
-    ///
-    /// This method panics when `n_best > 512` or when alpha is not a (normal)
-    /// positive floating point number.
+    /// The sampling configuration must match the type of the model:
+    /// `SamplingConfig::Unigram` for unigram models and
+    /// `SamplingConfig::BpeDropout` for BPE models. An error is returned
+    /// when the configuration does not match the model or when its
+    /// parameters are out of range.
     pub fn sample_encode(
         &self,
         sentence: &str,
-        n_best: usize,
-        alpha: f32,
+        config: SamplingConfig,
     ) -> Result<Vec<PieceWithId>, SentencePieceError> {
-        assert!(n_best <= 512);
-        assert!(alpha.is_normal() && alpha.is_positive());
+        config.validate(self.model_type())?;
+
+        let (n_best, alpha) = match config {
+            SamplingConfig::Unigram { n_best, alpha } => {
+                // sentencepiece samples from all segmentations when n_best < 0.
+                (n_best.map(|n_best| n_best as i32).unwrap_or(-1), alpha)
+            }
+            SamplingConfig::BpeDropout { dropout } => (-1, dropout),
+        };
 
         let mut len = 0usize;
         let c_proto = unsafe {
             spp_sample_encode_as_serialized_proto(
                 self.inner,
                 sentence.as_ptr() as *const c_char,
-                sentence.as_bytes().len(),
+                sentence.len(),
                 &mut len,
                 n_best,
                 alpha,
@@ -417,6 +1324,127 @@ impl SentencePieceProcessor {
         Self::process_encode_protobuf(CData { data: c_proto, len })
     }
 
+    /// Sample multiple segmentations of a sentence with their scores.
+    ///
+    /// Draws `num_samples` segmentations, where `alpha` controls the
+    /// smoothness of the distribution. If `wor` is `true`, segmentations
+    /// are sampled without replacement, so that all returned segmentations
+    /// are distinct. If `include_best` is `true`, the best segmentation is
+    /// always included in the samples, this requires sampling without
+    /// replacement.
+    ///
+    /// This is only supported by unigram models. `num_samples` must be in
+    /// `[1, i32::MAX]` and `alpha` must be finite.
+    pub fn sample_encode_and_score(
+        &self,
+        sentence: &str,
+        num_samples: usize,
+        alpha: f32,
+        wor: bool,
+        include_best: bool,
+    ) -> Result<Vec<Segmentation>, SentencePieceError> {
+        if self.model_type() != ModelType::Unigram {
+            return Err(SentencePieceError::UnsupportedSampling(self.model_type()));
+        }
+        let c_num_samples = match i32::try_from(num_samples) {
+            Ok(c_num_samples) if c_num_samples > 0 => c_num_samples,
+            _ => return Err(SentencePieceError::InvalidNumSamples(num_samples)),
+        };
+        if !alpha.is_finite() {
+            return Err(SentencePieceError::InvalidSamplingAlpha);
+        }
+        if include_best && !wor {
+            return Err(SentencePieceError::InvalidIncludeBest);
+        }
+
+        let mut len = 0usize;
+        let c_proto = unsafe {
+            spp_sample_encode_and_score_as_serialized_proto(
+                self.inner,
+                sentence.as_ptr() as *const c_char,
+                sentence.len(),
+                &mut len,
+                c_num_samples,
+                alpha,
+                wor,
+                include_best,
+            )
+        };
+
+        Self::process_nbest_encode_protobuf(CData { data: c_proto, len })
+    }
+
+    /// Get the score of the piece with the given identifier.
+    ///
+    /// For unigram models, this is the log probability of the piece.
+    pub fn score(&self, id: u32) -> Result<f32, SentencePieceError> {
+        self.check_id(id)?;
+        Ok(unsafe { spp_get_score(self.inner, id as i32) })
+    }
+
+    /// Remove the vocabulary restriction.
+    ///
+    /// This undoes the restriction set by [`set_vocabulary`](Self::set_vocabulary)
+    /// or [`load_vocabulary`](Self::load_vocabulary).
+    pub fn reset_vocabulary(&mut self) -> Result<(), SentencePieceError> {
+        let status = unsafe { spp_reset_vocabulary(self.inner) };
+        check_status(status)
+    }
+
+    /// Set the options that are applied when decoding a sentence.
+    pub fn set_decode_options(&mut self, options: DecodeOptions) -> Result<(), SentencePieceError> {
+        let extra_options = options.to_extra_options();
+        let status = unsafe { spp_set_decode_extra_options(self.inner, extra_options.as_ptr()) };
+        check_status(status)?;
+
+        self.decode_options = options;
+
+        Ok(())
+    }
+
+    /// Set the options that are applied when encoding a sentence.
+    ///
+    /// The options are applied by all encoding and sampling methods.
+    pub fn set_encode_options(&mut self, options: EncodeOptions) -> Result<(), SentencePieceError> {
+        let extra_options = options.to_extra_options();
+        let status = unsafe { spp_set_encode_extra_options(self.inner, extra_options.as_ptr()) };
+        check_status(status)?;
+
+        self.encode_options = options;
+
+        Ok(())
+    }
+
+    /// Restrict segmentation to the given vocabulary.
+    ///
+    /// Normal pieces that are not in the vocabulary are marked as unused,
+    /// so that they are not produced by segmentation. Pieces that consist
+    /// of a single character are always retained. This is only supported by
+    /// unigram and BPE models.
+    pub fn set_vocabulary(&mut self, pieces: &[impl AsRef<str>]) -> Result<(), SentencePieceError> {
+        let owned_c_pieces = pieces
+            .iter()
+            .map(|piece| CString::new(piece.as_ref()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| SentencePieceError::PieceContainsNul)?;
+        let c_pieces = owned_c_pieces
+            .iter()
+            .map(|piece| piece.as_ptr())
+            .collect::<Vec<_>>();
+
+        let status = unsafe { spp_set_vocabulary(self.inner, c_pieces.as_ptr(), c_pieces.len()) };
+        check_status(status)
+    }
+
+    /// Get a snapshot of the vocabulary of the model.
+    ///
+    /// The snapshot reflects the vocabulary at the time of the call,
+    /// including pieces that are marked as unused by
+    /// [`set_vocabulary`](Self::set_vocabulary).
+    pub fn vocab(&self) -> Vocab {
+        Vocab::from_model_proto(self.model_proto())
+    }
+
     pub fn unk_id(&self) -> u32 {
         let unk_id = unsafe { spp_unk_id(self.inner) };
         // unk_id must always be present.
@@ -434,9 +1462,27 @@ unsafe impl Sync for SentencePieceProcessor {}
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
-
-    use crate::{CSentencePieceError, PieceWithId, SentencePieceError, SentencePieceProcessor};
+    use std::convert::TryFrom;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::{env, process, thread};
+
+    use log::Level;
+    use prost::Message;
+
+    use crate::gguf::{self, GgufValue};
+    use crate::lint::{self, LintIssue, LintSeverity};
+    use crate::normalization_rules::{unit, DoubleArrayBuilder};
+    use crate::sentencepiece_model::model_proto::sentence_piece;
+    use crate::sentencepiece_model::{
+        self_test_data, trainer_spec, ModelProto, NormalizerSpec, SelfTestData,
+    };
+    use crate::{
+        huggingface, set_log_output, CSentencePieceError, DecodeOptions, EncodeOptions, LogOutput,
+        ModelEditor, ModelInfo, ModelType, NormalizationRules, Normalizer, PieceType, PieceWithId,
+        SamplingConfig, SentencePieceError, SentencePieceProcessor, TrainerOptions, Vocab,
+    };
 
     fn toy_model_proto() -> &'static [u8] {
         include_bytes!("../testdata/toy.model")
@@ -446,6 +1492,41 @@ mod tests {
         SentencePieceProcessor::from_serialized_proto(toy_model_proto())
     }
 
+    fn synthetic_corpus() -> Vec<String> {
+        let subjects = [
+            "the cat",
+            "a dog",
+            "my neighbour",
+            "the old farmer",
+            "a small bird",
+        ];
+        let verbs = ["sees", "likes", "chases", "ignores", "feeds"];
+        let objects = [
+            "the ball",
+            "a red apple",
+            "the garden",
+            "some water",
+            "the moon",
+        ];
+        (0..300)
+            .map(|i| {
+                format!(
+                    "{} {} {} on day {}.",
+                    subjects[i % 5],
+                    verbs[(i / 5) % 5],
+                    objects[(i / 25) % 5],
+                    i
+                )
+            })
+            .collect()
+    }
+
+    fn test_output_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("sentencepiece-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
     #[test]
     fn decodes_piece_ids_with_toy_model() {
         let model = toy_model().unwrap();
@@ -558,74 +1639,478 @@ mod tests {
     }
 
     #[test]
-    fn sample_encodes_sentence_with_toy_model() {
+    fn full_encodes_sentence_with_toy_model() {
         let model = toy_model().unwrap();
-        let pieces = model
-            .sample_encode("I saw a girl with a telescope.", 10, 0.5)
-            .unwrap();
-        // Since sampling is randomized, we cannot check the output,
-        // instead check that we can decode the result.
-        let pieces = pieces.iter().map(|p| p.id).collect::<Vec<_>>();
+        let sentence = "I saw a girl with a telescope.";
+        let encoded = model.encode_full(sentence).unwrap();
+
+        assert_eq!(encoded.normalized, "▁I▁saw▁a▁girl▁with▁a▁telescope.");
         assert_eq!(
-            model.decode_piece_ids(&pieces).unwrap(),
-            "I saw a girl with a telescope."
+            encoded
+                .pieces
+                .iter()
+                .map(|p| p.surface.as_str())
+                .collect::<Vec<_>>(),
+            vec!["I", " saw", " a", " girl", " with", " a", " t", "el", "es", "c", "o", "pe", "."]
+        );
+        assert!(encoded
+            .pieces
+            .iter()
+            .all(|p| p.piece_type == PieceType::Normal));
+
+        let pieces = model.encode(sentence).unwrap();
+        assert_eq!(
+            encoded
+                .pieces
+                .iter()
+                .map(|p| (p.piece.clone(), p.id, p.span))
+                .collect::<Vec<_>>(),
+            pieces
+                .into_iter()
+                .map(|p| (p.piece, p.id, p.span))
+                .collect::<Vec<_>>()
         );
     }
 
     #[test]
-    #[should_panic]
-    fn sample_encode_with_incorrect_alpha_fails() {
+    fn full_encodes_unknown_piece() {
         let model = toy_model().unwrap();
-        model
-            .sample_encode("I saw a girl with a telescope.", 10, 0.0)
-            .unwrap();
+        let encoded = model.encode_full("Test\0 nul").unwrap();
+        assert_eq!(encoded.pieces[2].surface, "\0");
+        assert_eq!(encoded.pieces[2].piece_type, PieceType::Unknown);
+        assert_eq!(encoded.normalized, "▁Test\0▁nul");
     }
 
     #[test]
-    #[should_panic]
-    fn sample_encode_with_incorrect_n_best_fails() {
-        let model = toy_model().unwrap();
+    fn full_encodes_normalized_sentence_with_options() {
+        let mut model = toy_model().unwrap();
         model
-            .sample_encode("I saw a girl with a telescope.", 513, 0.1)
+            .set_encode_options(EncodeOptions {
+                add_bos: true,
+                add_eos: true,
+                reverse: true,
+                unk_piece: true,
+            })
             .unwrap();
+
+        let encoded = model.encode_full("Ｉ saw\0").unwrap();
+        assert_eq!(encoded.pieces[0].piece_type, PieceType::Control);
+        assert_eq!(encoded.pieces[1].piece, "<unk>");
+        assert_eq!(encoded.normalized, "▁I▁saw\0");
+
+        // Unknown text is normalized as well, half-width katakana are
+        // folded to full-width.
+        let encoded = model.encode_full("ｱ saw").unwrap();
+        assert!(encoded
+            .pieces
+            .iter()
+            .any(|piece| piece.piece_type == PieceType::Unknown && piece.surface == "ｱ"));
+        assert_eq!(encoded.normalized, "▁ア▁saw");
     }
 
     #[test]
-    fn errors_on_path_with_nul() {
-        let test_path = Path::new("test\0path");
+    fn encodes_sentence_as_ids_with_toy_model() {
+        let model = toy_model().unwrap();
         assert_eq!(
-            SentencePieceProcessor::open(test_path).unwrap_err(),
-            SentencePieceError::FilenameContainsNul(test_path.to_owned())
+            model.encode_ids("I saw a girl with a telescope.").unwrap(),
+            vec![8, 465, 10, 947, 41, 10, 170, 168, 110, 28, 20, 143, 4]
         );
     }
 
     #[test]
-    fn fails_loading_nonexisting_model() {
+    fn encodes_sentence_as_pieces_with_toy_model() {
+        let model = toy_model().unwrap();
         assert_eq!(
-            SentencePieceProcessor::open("non-existing").unwrap_err(),
-            SentencePieceError::CError(CSentencePieceError::NotFound)
+            model
+                .encode_pieces("I saw a girl with a telescope.")
+                .unwrap(),
+            vec![
+                "▁I", "▁saw", "▁a", "▁girl", "▁with", "▁a", "▁t", "el", "es", "c", "o", "pe", "."
+            ]
         );
     }
 
     #[test]
-    fn handles_nul_character() {
+    fn encodes_empty_sentence_as_ids_and_pieces() {
         let model = toy_model().unwrap();
-        assert_eq!(
-            model.encode("Test\0 nul").unwrap(),
-            vec![
-                PieceWithId {
-                    piece: "▁T".to_string(),
-                    id: 239,
-                    span: (0, 1)
-                },
-                PieceWithId {
-                    piece: "est".to_string(),
-                    id: 382,
-                    span: (1, 4)
+        assert!(model.encode_ids("").unwrap().is_empty());
+        assert!(model.encode_pieces("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn nbest_encodes_sentence_with_toy_model() {
+        let model = toy_model().unwrap();
+        let sentence = "I saw a girl with a telescope.";
+        let segmentations = model.nbest_encode(sentence, 5).unwrap();
+        assert_eq!(segmentations.len(), 5);
+
+        // The first segmentation is the best segmentation.
+        assert_eq!(segmentations[0].pieces, model.encode(sentence).unwrap());
+
+        for pair in segmentations.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+            assert_ne!(pair[0].pieces, pair[1].pieces);
+        }
+
+        for segmentation in &segmentations {
+            let ids = segmentation.pieces.iter().map(|p| p.id).collect::<Vec<_>>();
+            assert_eq!(model.decode_piece_ids(&ids).unwrap(), sentence);
+        }
+    }
+
+    #[test]
+    fn sample_encodes_sentence_with_toy_model() {
+        let model = toy_model().unwrap();
+        let pieces = model
+            .sample_encode(
+                "I saw a girl with a telescope.",
+                SamplingConfig::Unigram {
+                    n_best: Some(10),
+                    alpha: 0.5,
                 },
-                PieceWithId {
-                    piece: "\u{0}".to_string(),
-                    id: 0,
+            )
+            .unwrap();
+        // Since sampling is randomized, we cannot check the output,
+        // instead check that we can decode the result.
+        let pieces = pieces.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(
+            model.decode_piece_ids(&pieces).unwrap(),
+            "I saw a girl with a telescope."
+        );
+    }
+
+    #[test]
+    fn sample_encodes_and_scores_sentence_with_toy_model() {
+        let model = toy_model().unwrap();
+        let sentence = "I saw a girl with a telescope.";
+        let segmentations = model
+            .sample_encode_and_score(sentence, 5, 0.5, true, true)
+            .unwrap();
+        assert_eq!(segmentations.len(), 5);
+
+        // Sampling without replacement only returns distinct segmentations.
+        for (idx, segmentation) in segmentations.iter().enumerate() {
+            assert!(segmentations[idx + 1..]
+                .iter()
+                .all(|other| other.pieces != segmentation.pieces));
+        }
+
+        let best = model.encode(sentence).unwrap();
+        assert!(segmentations.iter().any(|s| s.pieces == best));
+
+        for segmentation in &segmentations {
+            let ids = segmentation.pieces.iter().map(|p| p.id).collect::<Vec<_>>();
+            assert_eq!(model.decode_piece_ids(&ids).unwrap(), sentence);
+        }
+    }
+
+    #[test]
+    fn sample_encodes_sentence_from_all_segmentations() {
+        let model = toy_model().unwrap();
+        let pieces = model
+            .sample_encode(
+                "I saw a girl with a telescope.",
+                SamplingConfig::Unigram {
+                    n_best: None,
+                    alpha: 0.1,
+                },
+            )
+            .unwrap();
+        let pieces = pieces.iter().map(|p| p.id).collect::<Vec<_>>();
+        assert_eq!(
+            model.decode_piece_ids(&pieces).unwrap(),
+            "I saw a girl with a telescope."
+        );
+    }
+
+    #[cfg(all(feature = "static", not(feature = "system")))]
+    #[test]
+    fn seeded_sampling_is_reproducible() {
+        use crate::with_random_generator_seed;
+
+        let model = toy_model().unwrap();
+        let sentence = "I saw a girl with a telescope.";
+        let sample = |seed| {
+            with_random_generator_seed(seed, || {
+                let samples = (0..10)
+                    .map(|_| {
+                        model
+                            .sample_encode(
+                                sentence,
+                                SamplingConfig::Unigram {
+                                    n_best: None,
+                                    alpha: 0.1,
+                                },
+                            )
+                            .unwrap()
+                    })
+                    .collect::<Vec<_>>();
+                let scored = model
+                    .sample_encode_and_score(sentence, 5, 0.1, false, false)
+                    .unwrap();
+                (samples, scored)
+            })
+        };
+
+        assert_eq!(sample(42), sample(42));
+        assert_eq!(sample(1337), sample(1337));
+        assert_ne!(sample(42), sample(1337));
+
+        let pieces = with_random_generator_seed(42, || {
+            model
+                .sample_encode(
+                    sentence,
+                    SamplingConfig::Unigram {
+                        n_best: None,
+                        alpha: 0.1,
+                    },
+                )
+                .unwrap()
+        });
+        assert_eq!(
+            pieces.iter().map(|p| p.piece.as_str()).collect::<Vec<_>>(),
+            vec![
+                "▁", "I", "▁saw", "▁", "a", "▁girl", "▁with", "▁a", "▁t", "el", "e", "s", "c",
+                "op", "e", "."
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_encode_and_score_validates_arguments() {
+        let model = toy_model().unwrap();
+        let sentence = "I saw a girl with a telescope.";
+        assert_eq!(
+            model.sample_encode_and_score(sentence, 0, 0.5, true, false),
+            Err(SentencePieceError::InvalidNumSamples(0))
+        );
+        assert_eq!(
+            model.sample_encode_and_score(sentence, usize::MAX, 0.5, true, false),
+            Err(SentencePieceError::InvalidNumSamples(usize::MAX))
+        );
+        assert_eq!(
+            model.sample_encode_and_score(sentence, 5, f32::NAN, true, false),
+            Err(SentencePieceError::InvalidSamplingAlpha)
+        );
+        assert_eq!(
+            model.sample_encode_and_score(sentence, 5, 0.5, false, true),
+            Err(SentencePieceError::InvalidIncludeBest)
+        );
+    }
+
+    #[test]
+    fn sample_encode_with_incorrect_alpha_fails() {
+        let model = toy_model().unwrap();
+        assert_eq!(
+            model.sample_encode(
+                "I saw a girl with a telescope.",
+                SamplingConfig::Unigram {
+                    n_best: Some(10),
+                    alpha: 0.0
+                },
+            ),
+            Err(SentencePieceError::InvalidSamplingAlpha)
+        );
+    }
+
+    #[test]
+    fn sample_encode_with_incorrect_n_best_fails() {
+        let model = toy_model().unwrap();
+        assert_eq!(
+            model.sample_encode(
+                "I saw a girl with a telescope.",
+                SamplingConfig::Unigram {
+                    n_best: Some(513),
+                    alpha: 0.1
+                },
+            ),
+            Err(SentencePieceError::InvalidNBest(513))
+        );
+    }
+
+    #[test]
+    fn sample_encode_with_incorrect_model_type_fails() {
+        let model = toy_model().unwrap();
+        assert_eq!(
+            model.sample_encode(
+                "I saw a girl with a telescope.",
+                SamplingConfig::BpeDropout { dropout: 0.1 },
+            ),
+            Err(SentencePieceError::UnsupportedSampling(ModelType::Unigram))
+        );
+    }
+
+    #[test]
+    fn toy_model_is_unigram_model() {
+        let model = toy_model().unwrap();
+        assert_eq!(model.model_type(), ModelType::Unigram);
+    }
+
+    #[test]
+    fn encodes_with_bos_and_eos() {
+        let mut model = toy_model().unwrap();
+        model
+            .set_encode_options(EncodeOptions {
+                add_bos: true,
+                add_eos: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let sentence = "I saw a girl.";
+        let pieces = model.encode(sentence).unwrap();
+        assert_eq!(
+            pieces.first(),
+            Some(&PieceWithId {
+                piece: "<s>".to_string(),
+                id: 1,
+                span: (0, 0),
+            })
+        );
+        assert_eq!(
+            pieces.last(),
+            Some(&PieceWithId {
+                piece: "</s>".to_string(),
+                id: 2,
+                span: (13, 13),
+            })
+        );
+        assert_eq!(
+            model.encode_ids(sentence).unwrap(),
+            vec![1, 8, 465, 10, 947, 4, 2]
+        );
+        assert_eq!(model.decode_piece_ids(&[1, 8, 465, 2]).unwrap(), "I saw");
+    }
+
+    #[test]
+    fn encodes_and_decodes_reversed() {
+        let mut model = toy_model().unwrap();
+        model
+            .set_encode_options(EncodeOptions {
+                reverse: true,
+                ..Default::default()
+            })
+            .unwrap();
+        model
+            .set_decode_options(DecodeOptions {
+                reverse: true,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let ids = model.encode_ids("I saw a girl.").unwrap();
+        assert_eq!(ids, vec![4, 947, 10, 465, 8]);
+        assert_eq!(model.decode_piece_ids(&ids).unwrap(), "I saw a girl.");
+    }
+
+    #[test]
+    fn decodes_with_unk_surface() {
+        let mut model = toy_model().unwrap();
+        model
+            .set_decode_options(DecodeOptions {
+                unk_surface: Some("<?>".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(model.decode_piece_ids(&[8, 0, 465]).unwrap(), "I<?> saw");
+        assert_eq!(
+            model.decode_pieces(&["▁I", "<unk>", "▁saw"]).unwrap(),
+            "I<?> saw"
+        );
+        assert_eq!(
+            model
+                .decode_piece_ids(&[8, 465, 10, 947, 41, 10, 170, 168, 110, 28, 20, 143, 4])
+                .unwrap(),
+            "I saw a girl with a telescope."
+        );
+    }
+
+    #[test]
+    fn restricts_vocabulary() {
+        let mut model = toy_model().unwrap();
+        let sentence = "I saw a girl with a telescope.";
+        model.set_vocabulary(&["▁I", "▁girl"]).unwrap();
+
+        let pieces = model.encode_pieces(sentence).unwrap();
+        assert_eq!(pieces[0], "▁I");
+        assert!(pieces.contains(&"▁girl".to_string()));
+        assert!(pieces
+            .iter()
+            .all(|p| p == "▁I" || p == "▁girl" || p.chars().count() == 1));
+        let ids = model.encode_ids(sentence).unwrap();
+        assert_eq!(model.decode_piece_ids(&ids).unwrap(), sentence);
+
+        model.reset_vocabulary().unwrap();
+        assert_eq!(
+            model.encode_ids(sentence).unwrap(),
+            vec![8, 465, 10, 947, 41, 10, 170, 168, 110, 28, 20, 143, 4]
+        );
+    }
+
+    #[test]
+    fn loads_vocabulary_with_threshold() {
+        let vocab_path = std::env::temp_dir().join(format!(
+            "sentencepiece-test-vocab-{}.tsv",
+            std::process::id()
+        ));
+        std::fs::write(&vocab_path, "▁I\t5\n▁saw\t1\n").unwrap();
+
+        let mut model = toy_model().unwrap();
+        let result = model.load_vocabulary(&vocab_path, 2);
+        std::fs::remove_file(&vocab_path).unwrap();
+        result.unwrap();
+
+        let pieces = model.encode_pieces("I saw").unwrap();
+        assert_eq!(pieces[0], "▁I");
+        assert!(!pieces.contains(&"▁saw".to_string()));
+    }
+
+    #[test]
+    fn fails_loading_nonexisting_vocabulary() {
+        let mut model = toy_model().unwrap();
+        assert_eq!(
+            model.load_vocabulary("non-existing", 0),
+            Err(SentencePieceError::CError(CSentencePieceError::NotFound))
+        );
+    }
+
+    #[test]
+    fn errors_on_path_with_nul() {
+        let test_path = Path::new("test\0path");
+        assert_eq!(
+            SentencePieceProcessor::open(test_path).unwrap_err(),
+            SentencePieceError::FilenameContainsNul(test_path.to_owned())
+        );
+    }
+
+    #[test]
+    fn fails_loading_nonexisting_model() {
+        assert_eq!(
+            SentencePieceProcessor::open("non-existing").unwrap_err(),
+            SentencePieceError::CError(CSentencePieceError::NotFound)
+        );
+    }
+
+    #[test]
+    fn handles_nul_character() {
+        let model = toy_model().unwrap();
+        assert_eq!(
+            model.encode("Test\0 nul").unwrap(),
+            vec![
+                PieceWithId {
+                    piece: "▁T".to_string(),
+                    id: 239,
+                    span: (0, 1)
+                },
+                PieceWithId {
+                    piece: "est".to_string(),
+                    id: 382,
+                    span: (1, 4)
+                },
+                PieceWithId {
+                    piece: "\u{0}".to_string(),
+                    id: 0,
                     span: (4, 5)
                 },
                 PieceWithId {
@@ -659,6 +2144,42 @@ mod tests {
         assert_eq!(toy_model.piece_to_id("unknown"), Ok(None));
     }
 
+    #[test]
+    fn can_lookup_piece_by_id() {
+        let toy_model = toy_model().unwrap();
+        assert_eq!(toy_model.id_to_piece(143), Ok("pe".to_string()));
+        assert_eq!(toy_model.id_to_piece(0), Ok("<unk>".to_string()));
+        assert_eq!(toy_model.id_to_piece(1), Ok("<s>".to_string()));
+        assert_eq!(
+            toy_model.id_to_piece(1000),
+            Err(SentencePieceError::IdOutOfRange(1000))
+        );
+    }
+
+    #[test]
+    fn can_lookup_piece_score() {
+        let toy_model = toy_model().unwrap();
+        assert_eq!(toy_model.score(1), Ok(0.0));
+        assert!(toy_model.score(143).unwrap() < 0.0);
+        assert_eq!(
+            toy_model.score(1000),
+            Err(SentencePieceError::IdOutOfRange(1000))
+        );
+    }
+
+    #[test]
+    fn can_lookup_piece_type() {
+        let toy_model = toy_model().unwrap();
+        assert_eq!(toy_model.piece_type(0), Ok(PieceType::Unknown));
+        assert_eq!(toy_model.piece_type(1), Ok(PieceType::Control));
+        assert_eq!(toy_model.piece_type(2), Ok(PieceType::Control));
+        assert_eq!(toy_model.piece_type(143), Ok(PieceType::Normal));
+        assert_eq!(
+            toy_model.piece_type(u32::MAX),
+            Err(SentencePieceError::IdOutOfRange(u32::MAX))
+        );
+    }
+
     #[test]
     fn can_lookup_bos_id() {
         let toy_model = toy_model().unwrap();
@@ -690,6 +2211,108 @@ mod tests {
         assert_eq!(model.len(), 1000);
     }
 
+    #[test]
+    fn vocab_matches_model() {
+        let toy_model = toy_model().unwrap();
+        let vocab = toy_model.vocab();
+        assert_eq!(vocab.len(), toy_model.len());
+
+        for entry in &vocab {
+            assert_eq!(toy_model.id_to_piece(entry.id), Ok(entry.piece.clone()));
+            assert_eq!(toy_model.score(entry.id), Ok(entry.score));
+            assert_eq!(toy_model.piece_type(entry.id), Ok(entry.piece_type));
+            assert_eq!(vocab.piece_to_id(&entry.piece), Some(entry.id));
+        }
+
+        assert_eq!(vocab.id_to_piece(143), Some("pe"));
+        assert_eq!(vocab.piece_to_id("unknown"), None);
+        assert!(vocab.get(1000).is_none());
+    }
+
+    #[test]
+    fn writes_vocab_in_sentencepiece_format() {
+        let toy_model = toy_model().unwrap();
+        let mut tsv = Vec::new();
+        toy_model.vocab().write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+
+        let lines = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1000);
+        assert_eq!(&lines[..3], &["<unk>\t0", "<s>\t0", "</s>\t0"]);
+        assert!(lines[8].starts_with("▁I\t-"));
+
+        let vocab = Vocab::read_tsv(tsv.as_bytes()).unwrap();
+        assert_eq!(vocab.len(), 1000);
+        for entry in &vocab {
+            assert_eq!(toy_model.id_to_piece(entry.id).unwrap(), entry.piece);
+            let expected = toy_model.score(entry.id).unwrap();
+            assert!((entry.score - expected).abs() <= expected.abs() * 1e-5);
+        }
+
+        let mut roundtrip = Vec::new();
+        vocab.write_tsv(&mut roundtrip).unwrap();
+        assert_eq!(String::from_utf8(roundtrip).unwrap(), tsv);
+    }
+
+    #[test]
+    fn writes_vocab_without_scores() {
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model
+            .trainer_spec
+            .get_or_insert_with(Default::default)
+            .vocabulary_output_piece_score = Some(false);
+        model.pieces[8].piece = Some("▁I\tme".to_string());
+        let spp = SentencePieceProcessor::from_serialized_proto(&model.encode_to_vec()).unwrap();
+
+        let mut tsv = Vec::new();
+        spp.vocab().write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+
+        let lines = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 1000);
+        assert_eq!(&lines[..3], &["<unk>", "<s>", "</s>"]);
+        assert_eq!(lines[8], "▁I\tme");
+    }
+
+    #[test]
+    fn reads_frequency_file() {
+        let vocab = Vocab::read_tsv("▁I\t5\n▁saw\n".as_bytes()).unwrap();
+        assert_eq!(
+            vocab
+                .iter()
+                .map(|entry| (entry.piece.as_str(), entry.id, entry.score))
+                .collect::<Vec<_>>(),
+            vec![("▁I", 0, 5.), ("▁saw", 1, 1.)]
+        );
+        assert_eq!(vocab.piece_to_id("▁saw"), Some(1));
+        assert!(Vocab::read_tsv("\t5\n".as_bytes()).is_err());
+        assert!(Vocab::read_tsv("▁I\tfive\n".as_bytes()).is_err());
+
+        let vocab = Vocab::read_tsv("▁I\n▁saw\n".as_bytes()).unwrap();
+        let mut tsv = Vec::new();
+        vocab.write_tsv(&mut tsv).unwrap();
+        assert_eq!(tsv, b"\xe2\x96\x81I\n\xe2\x96\x81saw\n");
+    }
+
+    #[test]
+    fn vocab_can_be_shared_between_threads() {
+        let toy_model = toy_model().unwrap();
+        let vocab = Arc::new(toy_model.vocab());
+        let ids = toy_model.encode_ids("I saw a girl").unwrap();
+
+        let pieces = thread::scope(|s| {
+            s.spawn(|| {
+                ids.iter()
+                    .map(|&id| vocab.id_to_piece(id).unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .join()
+            .unwrap()
+        });
+
+        assert_eq!(pieces, toy_model.encode_pieces("I saw a girl").unwrap());
+    }
+
     #[test]
     fn protobuf_roundtrip_is_identical() {
         let protobuf = toy_model_proto();
@@ -697,6 +2320,1096 @@ mod tests {
         let protobuf_roundtrip = spp.to_serialized_proto();
         assert_eq!(protobuf, protobuf_roundtrip);
     }
+
+    #[test]
+    fn summarizes_model() {
+        let info = toy_model().unwrap().info();
+        assert_eq!(
+            info,
+            ModelInfo {
+                model_type: ModelType::Unigram,
+                vocab_size: 1000,
+                bos_id: Some(1),
+                eos_id: Some(2),
+                pad_id: None,
+                unk_id: 0,
+                normalizer_name: Some("nmt_nfkc".to_string()),
+                byte_fallback: false,
+                fingerprint: info.fingerprint,
+            }
+        );
+
+        let debug = format!("{:?}", toy_model().unwrap());
+        assert!(debug.contains("Unigram"));
+        assert!(debug.contains("nmt_nfkc"));
+        assert!(!debug.contains("inner"));
+    }
+
+    #[test]
+    fn fingerprint_identifies_model() {
+        let fingerprint = toy_model().unwrap().info().fingerprint;
+        assert_eq!(toy_model().unwrap().info().fingerprint, fingerprint);
+        assert_eq!(crate::info::fingerprint(toy_model_proto()), fingerprint);
+        assert_eq!(
+            SentencePieceProcessor::from_serialized_proto(toy_model_proto())
+                .unwrap()
+                .info()
+                .fingerprint,
+            fingerprint
+        );
+
+        // Fields that occur multiple times are merged when parsing, so
+        // this is a non-canonical serialization of the same model.
+        let model = ModelProto::decode(toy_model_proto()).unwrap();
+        let mut data = toy_model_proto().to_vec();
+        data.extend(
+            ModelProto {
+                normalizer_spec: model.normalizer_spec,
+                ..Default::default()
+            }
+            .encode_to_vec(),
+        );
+        assert_ne!(crate::info::fingerprint(&data), fingerprint);
+        assert_eq!(
+            SentencePieceProcessor::from_serialized_proto(&data)
+                .unwrap()
+                .info()
+                .fingerprint,
+            fingerprint
+        );
+
+        let mut editor = ModelEditor::new(&toy_model().unwrap());
+        editor.set_score("▁saw", -1.0).unwrap();
+        assert_ne!(editor.build().unwrap().info().fingerprint, fingerprint);
+    }
+
+    #[test]
+    fn loads_unknown_model_type_as_unigram() {
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model.trainer_spec.as_mut().unwrap().model_type = Some(42);
+
+        let spp = SentencePieceProcessor::from_serialized_proto(&model.encode_to_vec()).unwrap();
+        assert_eq!(spp.model_type(), ModelType::Unigram);
+    }
+
+    #[test]
+    fn rejects_model_with_non_utf8_piece() {
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model.pieces[143].piece = Some("<placeholder>".to_string());
+        let mut data = model.encode_to_vec();
+
+        // sentencepiece does not validate that pieces are UTF-8.
+        let placeholder = data
+            .windows(b"<placeholder>".len())
+            .position(|window| window == b"<placeholder>")
+            .unwrap();
+        data[placeholder] = 0xff;
+
+        assert!(matches!(
+            SentencePieceProcessor::from_serialized_proto(&data),
+            Err(SentencePieceError::InvalidModelProto(_))
+        ));
+    }
+
+    #[test]
+    fn fingerprint_is_fnv1a() {
+        assert_eq!(crate::info::fingerprint(b""), 0xcbf29ce484222325);
+        assert_eq!(crate::info::fingerprint(b"a"), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn exports_unigram_model_to_huggingface() {
+        let toy_model = toy_model().unwrap();
+        let tokenizer: serde_json::Value =
+            serde_json::from_str(&huggingface::to_tokenizer_json(&toy_model).unwrap()).unwrap();
+
+        let model = &tokenizer["model"];
+        assert_eq!(model["type"], "Unigram");
+        assert_eq!(model["unk_id"], 0);
+        let vocab = model["vocab"].as_array().unwrap();
+        assert_eq!(vocab.len(), toy_model.len());
+        for (id, entry) in vocab.iter().enumerate() {
+            let piece = entry[0].as_str().unwrap();
+            assert_eq!(toy_model.id_to_piece(id as u32), Ok(piece.to_string()));
+            if id as u32 != toy_model.unk_id() {
+                assert_eq!(toy_model.piece_to_id(piece), Ok(Some(id as u32)));
+            }
+        }
+
+        assert_eq!(tokenizer["pre_tokenizer"]["type"], "Metaspace");
+        assert_eq!(tokenizer["pre_tokenizer"]["prepend_scheme"], "always");
+        assert_eq!(
+            tokenizer["normalizer"]["normalizers"][0]["type"],
+            "Precompiled"
+        );
+
+        let added_tokens = tokenizer["added_tokens"].as_array().unwrap();
+        let special_ids = added_tokens
+            .iter()
+            .map(|token| token["id"].as_u64().unwrap() as u32)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            special_ids,
+            vec![
+                toy_model.unk_id(),
+                toy_model.bos_id().unwrap(),
+                toy_model.eos_id().unwrap()
+            ]
+        );
+        assert_eq!(added_tokens[1]["content"], "<s>");
+    }
+
+    #[test]
+    fn exports_bpe_model_to_huggingface() {
+        let mut model_proto = toy_model().unwrap().model_proto();
+        model_proto.trainer_spec.as_mut().unwrap().model_type =
+            Some(trainer_spec::ModelType::Bpe as i32);
+        let toy_model =
+            SentencePieceProcessor::from_serialized_proto(&model_proto.encode_to_vec()).unwrap();
+
+        let tokenizer: serde_json::Value =
+            serde_json::from_str(&huggingface::to_tokenizer_json(&toy_model).unwrap()).unwrap();
+
+        let model = &tokenizer["model"];
+        assert_eq!(model["type"], "BPE");
+        assert_eq!(model["unk_token"], "<unk>");
+        let vocab = model["vocab"].as_object().unwrap();
+        assert_eq!(vocab.len(), toy_model.len());
+        for (piece, id) in vocab {
+            assert_eq!(
+                toy_model.id_to_piece(id.as_u64().unwrap() as u32),
+                Ok(piece.clone())
+            );
+        }
+
+        let merges = model["merges"].as_array().unwrap();
+        assert!(!merges.is_empty());
+        for merge in merges {
+            let (left, right) = merge.as_str().unwrap().split_once(' ').unwrap();
+            assert!(vocab.contains_key(&format!("{}{}", left, right)));
+        }
+    }
+
+    #[test]
+    fn imports_unigram_model_from_huggingface() {
+        let toy_model = toy_model().unwrap();
+        let json = huggingface::to_tokenizer_json(&toy_model).unwrap();
+        let imported = huggingface::from_tokenizer_json(&json).unwrap();
+
+        assert_eq!(imported.len(), toy_model.len());
+        assert_eq!(imported.unk_id(), toy_model.unk_id());
+        assert_eq!(imported.bos_id(), toy_model.bos_id());
+        assert_eq!(imported.eos_id(), toy_model.eos_id());
+        assert_eq!(imported.model_type(), ModelType::Unigram);
+        for sentence in ["I saw a girl with a telescope.", "  Ｉ  saw  "] {
+            assert_eq!(
+                imported.encode_ids(sentence).unwrap(),
+                toy_model.encode_ids(sentence).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_huggingface_tokenizers() {
+        let json = huggingface::to_tokenizer_json(&toy_model().unwrap()).unwrap();
+        let tokenizer: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let mut lowercase = tokenizer.clone();
+        lowercase["normalizer"] = serde_json::json!({ "type": "Lowercase" });
+        assert_eq!(
+            huggingface::from_tokenizer_json(&lowercase.to_string()).unwrap_err(),
+            SentencePieceError::UnsupportedTokenizer("normalizer Lowercase".to_string())
+        );
+
+        let mut strip_left = tokenizer.clone();
+        let normalizers = strip_left["normalizer"]["normalizers"]
+            .as_array_mut()
+            .unwrap();
+        let strip = normalizers
+            .iter_mut()
+            .find(|normalizer| normalizer["type"] == "Strip")
+            .unwrap();
+        strip["strip_right"] = false.into();
+        assert_eq!(
+            huggingface::from_tokenizer_json(&strip_left.to_string()).unwrap_err(),
+            SentencePieceError::UnsupportedTokenizer("normalizer Strip".to_string())
+        );
+
+        let mut byte_level = tokenizer.clone();
+        byte_level["pre_tokenizer"] = serde_json::json!({ "type": "ByteLevel" });
+        assert_eq!(
+            huggingface::from_tokenizer_json(&byte_level.to_string()).unwrap_err(),
+            SentencePieceError::UnsupportedTokenizer("pre-tokenizer ByteLevel".to_string())
+        );
+
+        let mut bpe = tokenizer;
+        bpe["model"]["type"] = "BPE".into();
+        assert_eq!(
+            huggingface::from_tokenizer_json(&bpe.to_string()).unwrap_err(),
+            SentencePieceError::UnsupportedTokenizer("model type BPE".to_string())
+        );
+
+        assert!(matches!(
+            huggingface::from_tokenizer_json("{"),
+            Err(SentencePieceError::InvalidTokenizerJson(_))
+        ));
+    }
+
+    #[test]
+    fn exports_gguf_tokenizer_metadata() {
+        let toy_model = toy_model().unwrap();
+        let metadata = gguf::tokenizer_metadata(&toy_model, Some(true), None).unwrap();
+        let get = |key: &str| {
+            metadata
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, value)| value.clone())
+        };
+
+        assert_eq!(
+            get("tokenizer.ggml.model"),
+            Some(GgufValue::String("llama".to_string()))
+        );
+        match get("tokenizer.ggml.tokens") {
+            Some(GgufValue::Array(tokens)) => {
+                assert_eq!(tokens.len(), 1000);
+                assert_eq!(tokens[143], GgufValue::String("pe".to_string()));
+            }
+            value => panic!("Unexpected tokens: {:?}", value),
+        }
+        match get("tokenizer.ggml.token_type") {
+            Some(GgufValue::Array(token_types)) => {
+                assert_eq!(token_types[0], GgufValue::Int32(PieceType::Unknown as i32));
+                assert_eq!(token_types[1], GgufValue::Int32(PieceType::Control as i32));
+                assert_eq!(token_types[143], GgufValue::Int32(PieceType::Normal as i32));
+            }
+            value => panic!("Unexpected token types: {:?}", value),
+        }
+        match get("tokenizer.ggml.scores") {
+            Some(GgufValue::Array(scores)) => {
+                assert_eq!(
+                    scores[143],
+                    GgufValue::Float32(toy_model.score(143).unwrap())
+                )
+            }
+            value => panic!("Unexpected scores: {:?}", value),
+        }
+        assert_eq!(
+            get("tokenizer.ggml.bos_token_id"),
+            Some(GgufValue::UInt32(1))
+        );
+        assert_eq!(
+            get("tokenizer.ggml.eos_token_id"),
+            Some(GgufValue::UInt32(2))
+        );
+        assert_eq!(
+            get("tokenizer.ggml.unknown_token_id"),
+            Some(GgufValue::UInt32(0))
+        );
+        assert_eq!(get("tokenizer.ggml.padding_token_id"), None);
+        assert_eq!(
+            get("tokenizer.ggml.add_bos_token"),
+            Some(GgufValue::Bool(true))
+        );
+        assert_eq!(get("tokenizer.ggml.add_eos_token"), None);
+    }
+
+    #[test]
+    fn writes_gguf_file() {
+        let metadata = vec![
+            (
+                "tokenizer.ggml.model".to_string(),
+                GgufValue::String("llama".to_string()),
+            ),
+            (
+                "tokenizer.ggml.scores".to_string(),
+                GgufValue::Array(vec![GgufValue::Float32(0.), GgufValue::Float32(-1.)]),
+            ),
+        ];
+        let mut data = Vec::new();
+        gguf::write_gguf(&metadata, &mut data).unwrap();
+
+        let mut expected = Vec::new();
+        expected.extend_from_slice(b"GGUF");
+        expected.extend_from_slice(&3u32.to_le_bytes());
+        expected.extend_from_slice(&0u64.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&20u64.to_le_bytes());
+        expected.extend_from_slice(b"tokenizer.ggml.model");
+        expected.extend_from_slice(&8u32.to_le_bytes());
+        expected.extend_from_slice(&5u64.to_le_bytes());
+        expected.extend_from_slice(b"llama");
+        expected.extend_from_slice(&21u64.to_le_bytes());
+        expected.extend_from_slice(b"tokenizer.ggml.scores");
+        expected.extend_from_slice(&9u32.to_le_bytes());
+        expected.extend_from_slice(&6u32.to_le_bytes());
+        expected.extend_from_slice(&2u64.to_le_bytes());
+        expected.extend_from_slice(&0f32.to_le_bytes());
+        expected.extend_from_slice(&(-1f32).to_le_bytes());
+        expected.resize(128, 0);
+
+        assert_eq!(data, expected);
+
+        let mixed = vec![(
+            "mixed".to_string(),
+            GgufValue::Array(vec![GgufValue::UInt8(0), GgufValue::Int32(0)]),
+        )];
+        assert!(gguf::write_gguf(&mixed, Vec::new()).is_err());
+
+        let nested = vec![(
+            "nested".to_string(),
+            GgufValue::Array(vec![GgufValue::Array(vec![])]),
+        )];
+        assert!(gguf::write_gguf(&nested, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn loads_tokenizer_from_gguf() {
+        let toy_model = toy_model().unwrap();
+        let metadata = gguf::tokenizer_metadata(&toy_model, Some(true), None).unwrap();
+        let mut data = Vec::new();
+        gguf::write_gguf(&metadata, &mut data).unwrap();
+
+        let read_metadata = gguf::read_gguf(&*data).unwrap();
+        assert_eq!(read_metadata, metadata);
+
+        let model = gguf::tokenizer_from_metadata(&read_metadata).unwrap();
+        assert_eq!(model.model_type(), ModelType::Bpe);
+        assert_eq!(model.len(), toy_model.len());
+        for id in 0..model.len() as u32 {
+            assert_eq!(model.id_to_piece(id), toy_model.id_to_piece(id));
+            assert_eq!(model.score(id), toy_model.score(id));
+            assert_eq!(model.piece_type(id), toy_model.piece_type(id));
+        }
+        assert_eq!(model.bos_id(), Some(1));
+        assert_eq!(model.eos_id(), Some(2));
+        assert_eq!(model.unk_id(), 0);
+        assert_eq!(model.pad_id(), None);
+        assert!(model.encode_options().add_bos);
+
+        let sentence = "I saw a girl with a telescope.";
+        let ids = model.encode_ids(sentence).unwrap();
+        assert_eq!(ids[0], 1);
+        assert_eq!(model.decode_piece_ids(&ids).unwrap(), sentence);
+    }
+
+    #[test]
+    fn rejects_invalid_gguf_metadata() {
+        let metadata = gguf::tokenizer_metadata(&toy_model().unwrap(), None, None).unwrap();
+        let replace = |key: &str, value: Option<GgufValue>| {
+            let mut metadata = metadata.clone();
+            let idx = metadata.iter().position(|(k, _)| k == key).unwrap();
+            match value {
+                Some(value) => metadata[idx].1 = value,
+                None => {
+                    metadata.remove(idx);
+                }
+            }
+            gguf::tokenizer_from_metadata(&metadata).unwrap_err()
+        };
+
+        assert_eq!(
+            replace("tokenizer.ggml.tokens", None),
+            SentencePieceError::MissingMetadata("tokenizer.ggml.tokens".to_string())
+        );
+        assert_eq!(
+            replace(
+                "tokenizer.ggml.scores",
+                Some(GgufValue::Array(vec![GgufValue::Float32(0.)]))
+            ),
+            SentencePieceError::InconsistentMetadata("1000 tokens, but 1 scores".to_string())
+        );
+        assert_eq!(
+            replace(
+                "tokenizer.ggml.unknown_token_id",
+                Some(GgufValue::UInt32(1000))
+            ),
+            SentencePieceError::InconsistentMetadata(
+                "tokenizer.ggml.unknown_token_id is out of range: 1000".to_string()
+            )
+        );
+        assert_eq!(
+            replace(
+                "tokenizer.ggml.model",
+                Some(GgufValue::String("gpt2".to_string()))
+            ),
+            SentencePieceError::UnsupportedTokenizer("tokenizer model gpt2".to_string())
+        );
+
+        assert!(matches!(
+            gguf::read_gguf(&b"GGUF\x01\x00\x00\x00"[..]),
+            Err(SentencePieceError::InvalidGguf(_))
+        ));
+        assert!(matches!(
+            gguf::read_gguf(&b"GGML"[..]),
+            Err(SentencePieceError::InvalidGguf(_))
+        ));
+
+        // Deeply nested arrays must not exhaust the stack.
+        let mut nested = Vec::new();
+        nested.extend_from_slice(b"GGUF");
+        nested.extend_from_slice(&3u32.to_le_bytes());
+        nested.extend_from_slice(&0u64.to_le_bytes());
+        nested.extend_from_slice(&1u64.to_le_bytes());
+        nested.extend_from_slice(&6u64.to_le_bytes());
+        nested.extend_from_slice(b"nested");
+        nested.extend_from_slice(&9u32.to_le_bytes());
+        for _ in 0..100_000 {
+            nested.extend_from_slice(&9u32.to_le_bytes());
+            nested.extend_from_slice(&1u64.to_le_bytes());
+        }
+        assert_eq!(
+            gguf::read_gguf(&*nested),
+            Err(SentencePieceError::InvalidGguf(
+                "nested arrays are not supported".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn unedited_model_is_identical() {
+        let editor = ModelEditor::new(&toy_model().unwrap());
+        assert_eq!(
+            editor.build().unwrap().to_serialized_proto(),
+            toy_model_proto()
+        );
+    }
+
+    #[test]
+    fn editor_adds_user_defined_piece() {
+        let mut editor = ModelEditor::new(&toy_model().unwrap());
+        assert_eq!(
+            editor.add_piece("<sep>", 0.0, PieceType::UserDefined),
+            Ok(1000)
+        );
+        let model = editor.build().unwrap();
+
+        assert_eq!(model.len(), 1001);
+        assert_eq!(model.piece_type(1000), Ok(PieceType::UserDefined));
+        let pieces = model.encode_pieces("I saw<sep>a girl").unwrap();
+        assert!(pieces.contains(&"<sep>".to_string()));
+    }
+
+    #[test]
+    fn editor_removes_and_rescores_pieces() {
+        let mut editor = ModelEditor::new(&toy_model().unwrap());
+        editor.remove_piece("▁saw").unwrap();
+        editor.set_score("▁girl", -1.5).unwrap();
+        editor.set_piece_type("▁I", PieceType::Unused).unwrap();
+        let model = editor.build().unwrap();
+
+        assert_eq!(model.len(), 999);
+        assert_eq!(model.piece_to_id("▁saw"), Ok(None));
+        let girl_id = model.piece_to_id("▁girl").unwrap().unwrap();
+        assert_eq!(model.score(girl_id), Ok(-1.5));
+        let i_id = model.piece_to_id("▁I").unwrap().unwrap();
+        assert_eq!(model.piece_type(i_id), Ok(PieceType::Unused));
+        assert_ne!(model.encode_pieces("I").unwrap()[0], "▁I");
+    }
+
+    #[test]
+    fn editor_updates_special_ids_on_removal() {
+        let mut editor = ModelEditor::new(&toy_model().unwrap());
+        editor.remove_piece("<s>").unwrap();
+
+        let trainer_spec = editor.model_proto().trainer_spec.as_ref().unwrap();
+        assert_eq!(trainer_spec.unk_id(), 0);
+        assert_eq!(trainer_spec.bos_id(), -1);
+        assert_eq!(trainer_spec.eos_id(), 1);
+        assert_eq!(trainer_spec.pad_id(), -1);
+        assert_eq!(editor.model_proto().pieces[1].piece(), "</s>");
+    }
+
+    #[test]
+    fn editor_replaces_normalizer_spec() {
+        let toy_model = toy_model().unwrap();
+        let mut normalizer_spec = toy_model.model_proto().normalizer_spec.unwrap();
+        normalizer_spec.add_dummy_prefix = Some(false);
+
+        let mut editor = ModelEditor::new(&toy_model);
+        editor.set_normalizer_spec(normalizer_spec);
+        let model = editor.build().unwrap();
+
+        assert_eq!(model.encode_pieces("I saw").unwrap()[0], "I");
+    }
+
+    #[test]
+    fn editor_validates_edits() {
+        let mut editor = ModelEditor::new(&toy_model().unwrap());
+        assert_eq!(
+            editor.add_piece("▁saw", 0.0, PieceType::Normal),
+            Err(SentencePieceError::DuplicatePiece("▁saw".to_string()))
+        );
+        assert_eq!(
+            editor.add_piece("<unk2>", 0.0, PieceType::Unknown),
+            Err(SentencePieceError::UnknownPieceCount(2))
+        );
+        assert_eq!(
+            editor.remove_piece("<unk>"),
+            Err(SentencePieceError::UnknownPieceCount(0))
+        );
+        assert_eq!(
+            editor.set_piece_type("<unk>", PieceType::Normal),
+            Err(SentencePieceError::UnknownPieceCount(0))
+        );
+        assert_eq!(
+            editor.set_score("non-existing", 0.0),
+            Err(SentencePieceError::PieceNotFound(
+                "non-existing".to_string()
+            ))
+        );
+        assert_eq!(
+            editor.set_score("▁girl", f32::NAN),
+            Err(SentencePieceError::InvalidScore)
+        );
+        assert_eq!(
+            editor.add_piece("<sep>", f32::INFINITY, PieceType::UserDefined),
+            Err(SentencePieceError::InvalidScore)
+        );
+
+        // Failed edits must not change the model.
+        assert_eq!(
+            editor.build().unwrap().to_serialized_proto(),
+            toy_model_proto()
+        );
+
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model.pieces[20].piece = Some("▁saw".to_string());
+        assert_eq!(
+            ModelEditor::try_from(model.clone()).unwrap_err(),
+            SentencePieceError::DuplicatePiece("▁saw".to_string())
+        );
+        model.pieces.remove(20);
+        model.pieces[0].r#type = Some(PieceType::Normal as i32);
+        assert_eq!(
+            ModelEditor::try_from(model).unwrap_err(),
+            SentencePieceError::UnknownPieceCount(0)
+        );
+    }
+
+    #[test]
+    fn model_json_roundtrip_is_lossless() {
+        let json = toy_model().unwrap().model_proto().to_json();
+        let model = ModelProto::from_json(&json).unwrap();
+        assert_eq!(model.encode_to_vec(), toy_model_proto());
+
+        let spp = SentencePieceProcessor::from_json(&json).unwrap();
+        assert_eq!(spp.model_proto().to_json(), json);
+    }
+
+    #[test]
+    fn model_json_retains_non_finite_scores() {
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model.pieces[8].score = Some(f32::NEG_INFINITY);
+        model.pieces[9].score = Some(f32::NAN);
+        model.trainer_spec.as_mut().unwrap().character_coverage = Some(f32::INFINITY);
+
+        let json = model.to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["pieces"][8]["score"], "-Infinity");
+        assert_eq!(value["pieces"][9]["score"], "NaN");
+        assert_eq!(value["trainer_spec"]["character_coverage"], "Infinity");
+
+        let decoded = ModelProto::from_json(&json).unwrap();
+        assert_eq!(decoded.pieces[8].score, Some(f32::NEG_INFINITY));
+        assert!(decoded.pieces[9].score.unwrap().is_nan());
+        assert_eq!(
+            decoded.trainer_spec.unwrap().character_coverage,
+            Some(f32::INFINITY)
+        );
+
+        assert!(matches!(
+            ModelProto::from_json(r#"{"pieces": [{"score": "large"}]}"#),
+            Err(SentencePieceError::InvalidModelJson(_))
+        ));
+    }
+
+    #[test]
+    fn model_json_is_readable() {
+        let json = toy_model().unwrap().model_proto().to_json();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value["trainer_spec"]["model_type"], "UNIGRAM");
+        assert_eq!(value["normalizer_spec"]["name"], "nmt_nfkc");
+        assert!(value["normalizer_spec"]["precompiled_charsmap"].is_string());
+        assert_eq!(value["pieces"][0]["piece"], "<unk>");
+        assert_eq!(value["pieces"][0]["type"], "UNKNOWN");
+        assert_eq!(value["pieces"][1]["type"], "CONTROL");
+
+        assert!(matches!(
+            ModelProto::from_json(r#"{"pieces": [{"type": "SPECIAL"}]}"#),
+            Err(SentencePieceError::InvalidModelJson(_))
+        ));
+    }
+
+    #[test]
+    fn lints_toy_model_without_issues() {
+        assert_eq!(toy_model().unwrap().lint(), Vec::new());
+    }
+
+    #[test]
+    fn lints_broken_model() {
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model.pieces[5].piece = model.pieces[4].piece.clone();
+        model.pieces[10].score = Some(f32::NAN);
+        model.pieces[20].r#type = Some(sentence_piece::Type::Unknown as i32);
+        let trainer_spec = model.trainer_spec.as_mut().unwrap();
+        trainer_spec.eos_id = Some(1000);
+        trainer_spec.pad_id = Some(0);
+        trainer_spec.byte_fallback = Some(true);
+        model.normalizer_spec.as_mut().unwrap().name = Some("custom".to_string());
+
+        let issues = lint::lint_model(&model);
+        assert!(matches!(
+            issues[0],
+            LintIssue::NonFiniteScore { id: 10, score } if score.is_nan()
+        ));
+        assert_eq!(
+            issues[1..],
+            [
+                LintIssue::DuplicatePiece {
+                    piece: model.pieces[4].piece().to_string(),
+                    ids: vec![4, 5]
+                },
+                LintIssue::MultipleUnknownPieces { ids: vec![0, 20] },
+                LintIssue::SpecialIdOutOfRange {
+                    name: "eos_id",
+                    id: 1000
+                },
+                LintIssue::SpecialIdCollision {
+                    name: "pad_id",
+                    other: "unk_id",
+                    id: 0
+                },
+                LintIssue::SpecialPieceMismatch {
+                    name: "pad_id",
+                    id: 0,
+                    expected: "<pad>".to_string(),
+                    found: "<unk>".to_string()
+                },
+                LintIssue::MissingBytePieces {
+                    missing: (0..=255).collect()
+                },
+                LintIssue::UnusualNormalizer("unknown normalization rule name: custom".to_string()),
+            ]
+        );
+
+        let severities = issues.iter().map(LintIssue::severity).collect::<Vec<_>>();
+        assert_eq!(severities.iter().max(), Some(&LintSeverity::Error));
+        assert_eq!(issues[5].severity(), LintSeverity::Warning);
+        assert_eq!(
+            LintIssue::BytePiecesWithoutFallback.severity(),
+            LintSeverity::Warning
+        );
+    }
+
+    #[test]
+    fn trains_model_from_files() {
+        let dir = test_output_dir("train-files");
+        let corpus = dir.join("corpus.txt");
+        fs::write(&corpus, synthetic_corpus().join("\n")).unwrap();
+
+        TrainerOptions::new()
+            .vocab_size(100)
+            .user_defined_symbols(["<sep>"])
+            .num_threads(1)
+            .train(&[&corpus], dir.join("model"))
+            .unwrap();
+
+        let spp = SentencePieceProcessor::open(dir.join("model.model")).unwrap();
+        assert_eq!(spp.len(), 100);
+        assert_eq!(spp.model_type(), ModelType::Unigram);
+        assert_eq!(spp.piece_to_id("<sep>").unwrap(), Some(3));
+
+        let sentence = "the cat feeds the moon on day 42.";
+        let ids = spp
+            .encode(sentence)
+            .unwrap()
+            .into_iter()
+            .map(|piece| piece.id)
+            .collect::<Vec<_>>();
+        assert_eq!(spp.decode_piece_ids(&ids).unwrap(), sentence);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn trains_model_from_iterator() {
+        let corpus = synthetic_corpus();
+        for &model_type in &[ModelType::Unigram, ModelType::Bpe] {
+            let spp = TrainerOptions::new()
+                .model_type(model_type)
+                .vocab_size(100)
+                .num_threads(1)
+                .train_from_iter(&corpus)
+                .unwrap();
+            assert_eq!(spp.len(), 100);
+            assert_eq!(spp.model_type(), model_type);
+
+            for sentence in &corpus[..20] {
+                let ids = spp
+                    .encode(sentence)
+                    .unwrap()
+                    .into_iter()
+                    .map(|piece| piece.id)
+                    .collect::<Vec<_>>();
+                assert_eq!(&spp.decode_piece_ids(&ids).unwrap(), sentence);
+            }
+        }
+    }
+
+    #[test]
+    fn trains_serialized_model_from_iterator() {
+        let proto = TrainerOptions::new()
+            .vocab_size(100)
+            .num_threads(1)
+            .train_from_iter_as_serialized_proto(synthetic_corpus().iter().map(String::as_str))
+            .unwrap();
+        let model = ModelProto::decode(&*proto).unwrap();
+        assert_eq!(model.pieces.len(), 100);
+        assert_eq!(model.trainer_spec.unwrap().model_prefix(), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "corpus is unavailable")]
+    fn training_propagates_iterator_panics() {
+        let sentences = synthetic_corpus()
+            .into_iter()
+            .enumerate()
+            .map(|(i, sentence)| {
+                if i == 100 {
+                    panic!("corpus is unavailable");
+                }
+                sentence
+            });
+        let _ = TrainerOptions::new()
+            .vocab_size(100)
+            .num_threads(1)
+            .train_from_iter(sentences);
+    }
+
+    #[test]
+    fn training_reports_sentencepiece_errors() {
+        let dir = test_output_dir("train-missing");
+        let result = TrainerOptions::new()
+            .vocab_size(100)
+            .train(&[dir.join("missing.txt")], dir.join("model"));
+        assert!(matches!(
+            result,
+            Err(SentencePieceError::TrainError(_, message)) if message.contains("missing.txt")
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn forwards_log_messages_to_log_crate() {
+        struct TestLogger(Mutex<Vec<(Level, String)>>);
+
+        impl log::Log for TestLogger {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.target() == "sentencepiece"
+            }
+
+            fn log(&self, record: &log::Record) {
+                if self.enabled(record.metadata()) {
+                    let mut records = self.0.lock().unwrap();
+                    records.push((record.level(), record.args().to_string()));
+                }
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: TestLogger = TestLogger(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Info);
+
+        // sentencepiece logs the samples that fail the self-test.
+        let mut model = ModelProto::decode(toy_model_proto()).unwrap();
+        model.self_test_data = Some(SelfTestData {
+            samples: vec![self_test_data::Sample {
+                input: Some("I saw a girl".to_string()),
+                expected: Some("▁I ▁saw ▁a ▁boy".to_string()),
+            }],
+        });
+
+        set_log_output(LogOutput::Log);
+        let result = SentencePieceProcessor::from_serialized_proto(&model.encode_to_vec());
+        set_log_output(LogOutput::Stderr);
+
+        assert!(result.is_err());
+        let records = LOGGER.0.lock().unwrap();
+        assert!(records.iter().any(|(level, _)| *level == Level::Info));
+    }
+
+    #[test]
+    fn trainer_options_are_validated() {
+        let train = |options: TrainerOptions| options.train(&["corpus.txt"], "model");
+        let is_invalid =
+            |result| matches!(result, Err(SentencePieceError::InvalidTrainerOptions(_)));
+
+        assert!(is_invalid(train(TrainerOptions::new().vocab_size(0))));
+        assert!(is_invalid(train(
+            TrainerOptions::new().character_coverage(1.5)
+        )));
+        assert!(is_invalid(train(
+            TrainerOptions::new().character_coverage(f32::NAN)
+        )));
+        assert!(is_invalid(train(TrainerOptions::new().num_threads(0))));
+        assert!(is_invalid(train(
+            TrainerOptions::new().user_defined_symbols(["a,b"])
+        )));
+        assert!(is_invalid(train(
+            TrainerOptions::new()
+                .user_defined_symbols(["<sep>"])
+                .control_symbols(["<sep>"])
+        )));
+        assert!(is_invalid(train(TrainerOptions::new().pad_id(Some(0)))));
+        assert!(is_invalid(train(
+            TrainerOptions::new().vocab_size(100).bos_id(Some(100))
+        )));
+        assert!(is_invalid(
+            TrainerOptions::new().train(&["a,b.txt"], "model")
+        ));
+        assert!(is_invalid(
+            TrainerOptions::new().train(&[] as &[&str], "model")
+        ));
+    }
+
+    #[test]
+    fn normalizes_with_alignment() {
+        let toy_model = toy_model().unwrap();
+        let normalizer = Normalizer::new(&toy_model);
+
+        let text = "ＡＢ  c";
+        let (normalized, alignment) = normalizer.normalize(text).unwrap();
+        assert_eq!(normalized, "▁AB▁c");
+        assert_eq!(alignment.len(), normalized.len() + 1);
+        assert_eq!(alignment[normalized.find('A').unwrap()], 0);
+        assert_eq!(alignment[normalized.find('B').unwrap()], 3);
+        assert_eq!(alignment[normalized.find('c').unwrap()], 8);
+        assert_eq!(alignment.last(), Some(&text.len()));
+    }
+
+    #[test]
+    fn normalizes_with_normalizer_spec() {
+        let identity = Normalizer::from_normalizer_spec(NormalizerSpec {
+            name: Some("identity".to_string()),
+            add_dummy_prefix: Some(false),
+            remove_extra_whitespaces: Some(false),
+            escape_whitespaces: Some(false),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            identity.normalize("ＡＢ  c").unwrap(),
+            ("ＡＢ  c".to_string(), vec![0, 0, 0, 3, 3, 3, 6, 7, 8, 9])
+        );
+
+        let model = ModelProto::decode(toy_model_proto()).unwrap();
+        let nfkc = Normalizer::from_normalizer_spec(model.normalizer_spec.unwrap()).unwrap();
+        assert_eq!(nfkc.normalize("ＡＢ  c").unwrap().0, "▁AB▁c");
+    }
+
+    #[test]
+    fn decompiles_and_compiles_normalization_rules() {
+        let model = ModelProto::decode(toy_model_proto()).unwrap();
+        let rules =
+            NormalizationRules::from_normalizer_spec(&model.normalizer_spec.unwrap()).unwrap();
+        assert_eq!(rules.get("Ａ"), Some("A"));
+        assert_eq!(rules.get("ﬁ"), Some("fi"));
+        assert_eq!(rules.get("a"), None);
+
+        let charsmap = rules.to_precompiled_charsmap().unwrap();
+        assert_eq!(
+            NormalizationRules::from_precompiled_charsmap(&charsmap).unwrap(),
+            rules
+        );
+
+        let mut tsv = Vec::new();
+        rules.write_tsv(&mut tsv).unwrap();
+        assert_eq!(NormalizationRules::read_tsv(&*tsv).unwrap(), rules);
+    }
+
+    /// Search the double array like sentencepiece's normalizer, returning
+    /// the values of all keys that are a prefix of the text.
+    fn common_prefix_search(units: &[u32], text: &[u8]) -> Vec<(usize, u32)> {
+        let mut results = Vec::new();
+        let mut node_pos = 0;
+        for (idx, &label) in text.iter().enumerate() {
+            node_pos ^= unit::offset(units[node_pos]) ^ label as usize;
+            match units.get(node_pos) {
+                Some(&unit) if unit::label(unit) == label as u32 => {
+                    if unit::has_leaf(unit) {
+                        results.push((idx + 1, unit::value(units[node_pos ^ unit::offset(unit)])));
+                    }
+                }
+                _ => break,
+            }
+        }
+        results
+    }
+
+    #[test]
+    fn double_array_finds_prefixes() {
+        let keys: &[(&[u8], u32)] = &[(b"a", 1), (b"ab", 2), (b"abc", 3), (b"b", 4), (b"bd", 5)];
+        let units = DoubleArrayBuilder::build(keys.iter().copied()).unwrap();
+
+        assert_eq!(units.len() % 256, 0);
+        assert_eq!(
+            common_prefix_search(&units, b"abcd"),
+            vec![(1, 1), (2, 2), (3, 3)]
+        );
+        assert_eq!(common_prefix_search(&units, b"bd"), vec![(1, 4), (2, 5)]);
+        assert_eq!(common_prefix_search(&units, b"ba"), vec![(1, 4)]);
+        assert_eq!(common_prefix_search(&units, b"c"), vec![]);
+    }
+
+    #[test]
+    fn charsmap_roundtrip() {
+        let mut rules = NormalizationRules::new();
+        rules.insert("Ａ", "A");
+        rules.insert("ＡＢ", "AB");
+        rules.insert("ﬁ", "fi");
+        rules.insert("\u{200b}", "");
+        rules.insert("Ⅻ", "XII");
+
+        let charsmap = rules.to_precompiled_charsmap().unwrap();
+        assert_eq!(
+            NormalizationRules::from_precompiled_charsmap(&charsmap).unwrap(),
+            rules
+        );
+    }
+
+    #[test]
+    fn rejects_charsmap_with_exponential_paths() {
+        // Levels of two nodes that both have the two nodes of the next
+        // level as children, so that the number of paths doubles with every
+        // level.
+        const LEVELS: usize = 21;
+        let pair_base = |level: usize| 4 * (level + 1);
+        let mut units = vec![unit::inner(0xff, false, 0).unwrap(); pair_base(LEVELS)];
+        units[0] = unit::inner(0, false, pair_base(0)).unwrap();
+        for level in 0..LEVELS - 1 {
+            for label in [1, 2] {
+                let pos = pair_base(level) ^ label as usize;
+                units[pos] = unit::inner(label, false, pos ^ pair_base(level + 1)).unwrap();
+            }
+        }
+
+        let mut charsmap = ((units.len() * 4) as u32).to_le_bytes().to_vec();
+        charsmap.extend(units.iter().flat_map(|unit| unit.to_le_bytes()));
+        charsmap.push(0);
+
+        assert_eq!(
+            NormalizationRules::from_precompiled_charsmap(&charsmap),
+            Err(SentencePieceError::InvalidCharsMap(
+                "character map has too many nodes".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn normalization_rules_are_validated() {
+        assert!(NormalizationRules::read_tsv("FF21\tX\n".as_bytes()).is_err());
+        assert!(NormalizationRules::read_tsv("\t41\n".as_bytes()).is_err());
+        assert!(matches!(
+            NormalizationRules::from_precompiled_charsmap(&[4, 0, 0, 0, 1]),
+            Err(SentencePieceError::InvalidCharsMap(_))
+        ));
+
+        let mut rules = NormalizationRules::new();
+        rules.insert("a\0", "b");
+        assert!(matches!(
+            rules.to_precompiled_charsmap(),
+            Err(SentencePieceError::InvalidNormalizationRules(_))
+        ));
+    }
+
+    #[test]
+    fn editor_sets_normalization_rules() {
+        let toy_model = toy_model().unwrap();
+        let normalizer_spec = toy_model.model_proto().normalizer_spec.unwrap();
+        let mut rules = NormalizationRules::from_normalizer_spec(&normalizer_spec).unwrap();
+        rules.remove("Ａ");
+        rules.insert("ß", "ss");
+
+        let mut editor = ModelEditor::new(&toy_model);
+        editor.set_normalization_rules(&rules).unwrap();
+        let model = editor.build().unwrap();
+
+        let normalizer = Normalizer::new(&model);
+        assert_eq!(normalizer.normalize("ＡＢ ß").unwrap().0, "▁ＡB▁ss");
+
+        let normalizer_spec = model.model_proto().normalizer_spec.unwrap();
+        assert_eq!(normalizer_spec.name(), "user_defined");
+        assert_eq!(
+            NormalizationRules::from_normalizer_spec(&normalizer_spec).unwrap(),
+            rules
+        );
+    }
+
+    #[test]
+    fn editor_sets_normalization_rules_with_shared_suffixes() {
+        let mut rules = NormalizationRules::new();
+        rules.insert("qxz", "Q");
+        rules.insert("wxz", "Q");
+        rules.insert("qx", "K");
+        rules.insert("xz", "");
+        rules.insert("ß", "ss");
+        rules.insert("ﬁ", "fi");
+        rules.insert("\u{200b}", "");
+
+        let charsmap = rules.to_precompiled_charsmap().unwrap();
+        assert_eq!(
+            NormalizationRules::from_precompiled_charsmap(&charsmap).unwrap(),
+            rules
+        );
+
+        let toy_model = toy_model().unwrap();
+        let mut editor = ModelEditor::new(&toy_model);
+        editor.set_normalization_rules(&rules).unwrap();
+        let model = editor.build().unwrap();
+
+        let normalizer = Normalizer::new(&model);
+        assert_eq!(
+            normalizer
+                .normalize("qxz wxz xzq qxa ß\u{200b}ﬁ")
+                .unwrap()
+                .0,
+            "▁Q▁Q▁q▁Ka▁ssfi"
+        );
+    }
+
+    #[test]
+    fn model_proto_roundtrip_is_identical() {
+        let model = toy_model().unwrap().model_proto();
+        let spp = SentencePieceProcessor::from_serialized_proto(&model.encode_to_vec()).unwrap();
+        assert_eq!(spp.to_serialized_proto(), toy_model_proto());
+    }
+
+    #[test]
+    fn reads_model_proto() {
+        let model = toy_model().unwrap().model_proto();
+        assert_eq!(model.pieces.len(), 1000);
+        assert_eq!(model.pieces[0].piece.as_deref(), Some("<unk>"));
+        assert_eq!(model.pieces[0].r#type(), sentence_piece::Type::Unknown);
+        assert_eq!(model.pieces[143].r#type(), sentence_piece::Type::Normal);
+
+        let trainer_spec = model.trainer_spec.unwrap();
+        assert_eq!(trainer_spec.model_type(), trainer_spec::ModelType::Unigram);
+        assert_eq!(trainer_spec.vocab_size(), 1000);
+        assert!(!trainer_spec.byte_fallback());
+        assert_eq!(trainer_spec.unk_piece(), "<unk>");
+        assert_eq!(trainer_spec.bos_piece(), "<s>");
+        assert_eq!(trainer_spec.eos_piece(), "</s>");
+        assert_eq!(trainer_spec.pad_id(), -1);
+
+        let normalizer_spec = model.normalizer_spec.unwrap();
+        assert_eq!(normalizer_spec.name(), "nmt_nfkc");
+        assert!(normalizer_spec.add_dummy_prefix());
+    }
 }
 
 #[cfg(feature = "albert-tests")]
@@ -0,0 +1,316 @@
+//! Validation of sentencepiece models.
+//!
+//! The `sentencepiece` library accepts some models that are likely to be
+//! broken, such as models where the padding identifier collides with the
+//! unknown identifier. [`lint_model`] checks a model for such problems.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::sentencepiece_model::ModelProto;
+use crate::PieceType;
+
+/// Normalization rules that are built into sentencepiece.
+const BUILTIN_NORMALIZERS: &[&str] = &[
+    "identity",
+    "nfkc",
+    "nfkc_cf",
+    "nmt_nfkc",
+    "nmt_nfkc_cf",
+    "user_defined",
+];
+
+/// Severity of a lint issue.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LintSeverity {
+    /// The model is probably not what was intended.
+    Warning,
+
+    /// The model is broken or cannot be loaded.
+    Error,
+}
+
+/// Problem found in a model.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LintIssue {
+    /// Byte fallback is enabled, but not all byte pieces are present.
+    MissingBytePieces { missing: Vec<u8> },
+
+    /// Byte pieces are present, but byte fallback is disabled.
+    BytePiecesWithoutFallback,
+
+    /// The same piece occurs multiple times.
+    DuplicatePiece { piece: String, ids: Vec<u32> },
+
+    /// A piece is empty.
+    EmptyPiece { id: u32 },
+
+    /// The model does not have an unknown piece.
+    MissingUnknownPiece,
+
+    /// The model has more than one unknown piece.
+    MultipleUnknownPieces { ids: Vec<u32> },
+
+    /// A score is infinite or NaN.
+    NonFiniteScore { id: u32, score: f32 },
+
+    /// Two special pieces have the same identifier.
+    SpecialIdCollision {
+        name: &'static str,
+        other: &'static str,
+        id: i32,
+    },
+
+    /// The identifier of a special piece is outside the vocabulary.
+    SpecialIdOutOfRange { name: &'static str, id: i32 },
+
+    /// The identifier of a special piece refers to a different piece than
+    /// the special piece.
+    SpecialPieceMismatch {
+        name: &'static str,
+        id: i32,
+        expected: String,
+        found: String,
+    },
+
+    /// The normalizer uses unusual settings.
+    UnusualNormalizer(String),
+}
+
+impl LintIssue {
+    /// Get the severity of the issue.
+    pub fn severity(&self) -> LintSeverity {
+        match self {
+            LintIssue::BytePiecesWithoutFallback
+            | LintIssue::SpecialPieceMismatch { .. }
+            | LintIssue::UnusualNormalizer(_) => LintSeverity::Warning,
+            LintIssue::MissingBytePieces { .. }
+            | LintIssue::DuplicatePiece { .. }
+            | LintIssue::EmptyPiece { .. }
+            | LintIssue::MissingUnknownPiece
+            | LintIssue::MultipleUnknownPieces { .. }
+            | LintIssue::NonFiniteScore { .. }
+            | LintIssue::SpecialIdCollision { .. }
+            | LintIssue::SpecialIdOutOfRange { .. } => LintSeverity::Error,
+        }
+    }
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintIssue::MissingBytePieces { missing } => write!(
+                f,
+                "byte fallback is enabled, but {} byte pieces are missing",
+                missing.len()
+            ),
+            LintIssue::BytePiecesWithoutFallback => {
+                write!(f, "model has byte pieces, but byte fallback is disabled")
+            }
+            LintIssue::DuplicatePiece { piece, ids } => {
+                write!(f, "piece {} occurs multiple times: {:?}", piece, ids)
+            }
+            LintIssue::EmptyPiece { id } => write!(f, "piece {} is empty", id),
+            LintIssue::MissingUnknownPiece => write!(f, "model does not have an unknown piece"),
+            LintIssue::MultipleUnknownPieces { ids } => {
+                write!(f, "model has multiple unknown pieces: {:?}", ids)
+            }
+            LintIssue::NonFiniteScore { id, score } => {
+                write!(f, "piece {} has a non-finite score: {}", id, score)
+            }
+            LintIssue::SpecialIdCollision { name, other, id } => {
+                write!(f, "{} collides with {}: {}", name, other, id)
+            }
+            LintIssue::SpecialIdOutOfRange { name, id } => {
+                write!(f, "{} is out of range: {}", name, id)
+            }
+            LintIssue::SpecialPieceMismatch {
+                name,
+                id,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} {} refers to piece {}, expected {}",
+                name, id, found, expected
+            ),
+            LintIssue::UnusualNormalizer(message) => write!(f, "unusual normalizer: {}", message),
+        }
+    }
+}
+
+/// Check a model for problems.
+///
+/// The issues are returned in a fixed order, an empty vector means that no
+/// problems were found.
+pub fn lint_model(model: &ModelProto) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    lint_pieces(model, &mut issues);
+    lint_special_ids(model, &mut issues);
+    lint_byte_pieces(model, &mut issues);
+    lint_normalizer(model, &mut issues);
+    issues
+}
+
+fn piece_type(model: &ModelProto, id: usize) -> PieceType {
+    model.pieces[id]
+        .r#type
+        .and_then(PieceType::from_proto)
+        .unwrap_or(PieceType::Normal)
+}
+
+fn lint_pieces(model: &ModelProto, issues: &mut Vec<LintIssue>) {
+    let mut piece_ids: HashMap<&str, Vec<u32>> = HashMap::new();
+    let mut unk_ids = Vec::new();
+
+    for (id, piece) in model.pieces.iter().enumerate() {
+        if piece.piece().is_empty() {
+            issues.push(LintIssue::EmptyPiece { id: id as u32 });
+        } else {
+            piece_ids.entry(piece.piece()).or_default().push(id as u32);
+        }
+
+        if !piece.score().is_finite() {
+            issues.push(LintIssue::NonFiniteScore {
+                id: id as u32,
+                score: piece.score(),
+            });
+        }
+
+        if piece_type(model, id) == PieceType::Unknown {
+            unk_ids.push(id as u32);
+        }
+    }
+
+    let mut duplicates = piece_ids
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .collect::<Vec<_>>();
+    duplicates.sort_by_key(|(_, ids)| ids[0]);
+    issues.extend(
+        duplicates
+            .into_iter()
+            .map(|(piece, ids)| LintIssue::DuplicatePiece {
+                piece: piece.to_owned(),
+                ids,
+            }),
+    );
+
+    match unk_ids.len() {
+        0 => issues.push(LintIssue::MissingUnknownPiece),
+        1 => (),
+        _ => issues.push(LintIssue::MultipleUnknownPieces { ids: unk_ids }),
+    }
+}
+
+fn lint_special_ids(model: &ModelProto, issues: &mut Vec<LintIssue>) {
+    let trainer_spec = model.trainer_spec.clone().unwrap_or_default();
+    let special = [
+        ("unk_id", trainer_spec.unk_id(), trainer_spec.unk_piece()),
+        ("bos_id", trainer_spec.bos_id(), trainer_spec.bos_piece()),
+        ("eos_id", trainer_spec.eos_id(), trainer_spec.eos_piece()),
+        ("pad_id", trainer_spec.pad_id(), trainer_spec.pad_piece()),
+    ];
+
+    for (idx, &(name, id, expected)) in special.iter().enumerate() {
+        // Negative identifiers disable special pieces.
+        if id < 0 {
+            continue;
+        }
+
+        if let Some(&(other, _, _)) = special[..idx]
+            .iter()
+            .find(|&&(_, other_id, _)| other_id == id)
+        {
+            issues.push(LintIssue::SpecialIdCollision { name, other, id });
+        }
+
+        match model.pieces.get(id as usize) {
+            Some(piece) if piece.piece() != expected => {
+                issues.push(LintIssue::SpecialPieceMismatch {
+                    name,
+                    id,
+                    expected: expected.to_owned(),
+                    found: piece.piece().to_owned(),
+                })
+            }
+            Some(_) => (),
+            None => issues.push(LintIssue::SpecialIdOutOfRange { name, id }),
+        }
+    }
+}
+
+fn lint_byte_pieces(model: &ModelProto, issues: &mut Vec<LintIssue>) {
+    let byte_fallback = model
+        .trainer_spec
+        .as_ref()
+        .map(|spec| spec.byte_fallback())
+        .unwrap_or(false);
+
+    let mut present = [false; 256];
+    let mut has_byte_pieces = false;
+    for (id, piece) in model.pieces.iter().enumerate() {
+        if piece_type(model, id) != PieceType::Byte {
+            continue;
+        }
+
+        has_byte_pieces = true;
+        if let Some(byte) = piece
+            .piece()
+            .strip_prefix("<0x")
+            .and_then(|hex| hex.strip_suffix('>'))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            present[byte as usize] = true;
+        }
+    }
+
+    if byte_fallback {
+        let missing = (0..=255u8)
+            .filter(|&byte| !present[byte as usize])
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            issues.push(LintIssue::MissingBytePieces { missing });
+        }
+    } else if has_byte_pieces {
+        issues.push(LintIssue::BytePiecesWithoutFallback);
+    }
+}
+
+fn lint_normalizer(model: &ModelProto, issues: &mut Vec<LintIssue>) {
+    let normalizer_spec = match &model.normalizer_spec {
+        Some(normalizer_spec) => normalizer_spec,
+        None => {
+            issues.push(LintIssue::UnusualNormalizer(
+                "model does not have a normalizer specification".to_string(),
+            ));
+            return;
+        }
+    };
+
+    let name = normalizer_spec.name();
+    if !BUILTIN_NORMALIZERS.contains(&name) {
+        issues.push(LintIssue::UnusualNormalizer(format!(
+            "unknown normalization rule name: {}",
+            name
+        )));
+    }
+
+    let has_charsmap = normalizer_spec
+        .precompiled_charsmap
+        .as_ref()
+        .map(|charsmap| !charsmap.is_empty())
+        .unwrap_or(false);
+    if name != "identity" && !has_charsmap {
+        issues.push(LintIssue::UnusualNormalizer(format!(
+            "normalization rule {} does not have precompiled rules, no normalization is applied",
+            name
+        )));
+    }
+
+    if normalizer_spec.add_dummy_prefix() && !normalizer_spec.escape_whitespaces() {
+        issues.push(LintIssue::UnusualNormalizer(
+            "dummy prefix is added, but whitespace is not escaped".to_string(),
+        ));
+    }
+}
@@ -0,0 +1,133 @@
+use std::cell::Cell;
+use std::os::raw::c_uchar;
+use std::panic;
+use std::slice;
+
+use log::Level;
+use sentencepiece_sys::{spp_set_log_callback, spp_set_min_log_level};
+
+/// Severity of a log message of the `sentencepiece` library.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Info = 0,
+    Warning = 1,
+    Error = 2,
+    Fatal = 3,
+}
+
+/// Destination of the log messages of the `sentencepiece` library.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LogOutput {
+    /// Write log messages to standard error. This is the default.
+    Stderr,
+
+    /// Forward log messages to the [`log`](https://docs.rs/log) crate,
+    /// using the `sentencepiece` target.
+    Log,
+
+    /// Discard log messages.
+    Discard,
+}
+
+/// Set the minimum severity of messages that are logged by `sentencepiece`.
+///
+/// The default is [`LogLevel::Info`]. Use [`LogLevel::Fatal`] to only log
+/// messages that precede a crash.
+pub fn set_min_log_level(level: LogLevel) {
+    unsafe { spp_set_min_log_level(level as i32) }
+}
+
+/// Set the destination of the log messages of `sentencepiece`.
+///
+/// `sentencepiece` writes log messages to `std::cerr`, for example while
+/// loading a model or while training. Log messages are redirected by a
+/// stream buffer that is installed in `std::cerr` when the library is
+/// loaded, so the destination applies to all C++ code in the process that
+/// writes to `std::cerr`. The destination can be changed at any time, also
+/// while other threads are logging.
+///
+/// ```
+/// use sentencepiece::{set_log_output, LogOutput, SentencePieceProcessor};
+///
+/// set_log_output(LogOutput::Discard);
+/// let spp = SentencePieceProcessor::open("testdata/toy.model").unwrap();
+/// set_log_output(LogOutput::Stderr);
+/// ```
+pub fn set_log_output(output: LogOutput) {
+    let callback = match output {
+        LogOutput::Stderr => None,
+        LogOutput::Log => Some(log_line as unsafe extern "C" fn(*const c_uchar, usize)),
+        LogOutput::Discard => Some(discard_line as unsafe extern "C" fn(*const c_uchar, usize)),
+    };
+
+    unsafe { spp_set_log_callback(callback) }
+}
+
+thread_local! {
+    /// Level of the last log line. Continuation lines of multi-line log
+    /// messages are logged with this level.
+    static LAST_LEVEL: Cell<Level> = const { Cell::new(Level::Info) };
+}
+
+unsafe extern "C" fn log_line(line: *const c_uchar, line_len: usize) {
+    let line = String::from_utf8_lossy(slice::from_raw_parts(line, line_len));
+
+    // Panics of the logger must not unwind into C++.
+    let _ = panic::catch_unwind(|| {
+        let (level, message) = match parse_log_line(&line) {
+            Some((level, message)) => {
+                LAST_LEVEL.with(|last_level| last_level.set(level));
+                (level, message)
+            }
+            None => (LAST_LEVEL.with(Cell::get), &*line),
+        };
+
+        log::log!(target: "sentencepiece", level, "{}", message);
+    });
+}
+
+unsafe extern "C" fn discard_line(_line: *const c_uchar, _line_len: usize) {}
+
+/// Split a log line into its level and message.
+///
+/// `sentencepiece` log lines have the form
+/// `trainer_interface.cc(409) LOG(INFO) Loaded all 300 sentences`.
+fn parse_log_line(line: &str) -> Option<(Level, &str)> {
+    let start = line.find("LOG(")? + "LOG(".len();
+    let rest = &line[start..];
+    let end = rest.find(')')?;
+
+    let level = match &rest[..end] {
+        "INFO" => Level::Info,
+        "WARNING" => Level::Warn,
+        "ERROR" | "FATAL" => Level::Error,
+        _ => return None,
+    };
+
+    Some((level, rest[end + 1..].trim_start()))
+}
+
+#[cfg(test)]
+mod tests {
+    use log::Level;
+
+    use super::parse_log_line;
+
+    #[test]
+    fn parses_log_lines() {
+        assert_eq!(
+            parse_log_line("trainer_interface.cc(409) LOG(INFO) Loaded all 300 sentences"),
+            Some((Level::Info, "Loaded all 300 sentences"))
+        );
+        assert_eq!(
+            parse_log_line("trainer_interface.cc(140) LOG(WARNING) Too many sentences"),
+            Some((Level::Warn, "Too many sentences"))
+        );
+        assert_eq!(
+            parse_log_line("unigram_model_trainer.cc(78) LOG(FATAL) Check failed"),
+            Some((Level::Error, "Check failed"))
+        );
+        assert_eq!(parse_log_line("trainer_spec {"), None);
+        assert_eq!(parse_log_line("LOG(DEBUG) message"), None);
+    }
+}
@@ -0,0 +1,696 @@
+use std::collections::btree_map;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::io::{self, BufRead, Write};
+
+use crate::sentencepiece_model::NormalizerSpec;
+use crate::SentencePieceError;
+
+/// Maximum number of rules that can match a prefix of a text, imposed by
+/// the sentencepiece normalizer.
+const MAX_PREFIX_MATCHES: usize = 32;
+
+/// Maximum length of a rule source in bytes when decompiling.
+const MAX_SOURCE_LEN: usize = 1024;
+
+/// Maximum number of nodes that are visited when decompiling. Since nodes
+/// can be shared between rules, a crafted character map could otherwise
+/// take exponential time to decompile. The `nmt_nfkc` rules visit about
+/// 262,000 nodes.
+const MAX_DECOMPILE_NODES: usize = 1 << 20;
+
+/// Character normalization rules.
+///
+/// Normalization rules map a sequence of characters to its normalized
+/// form. During normalization, the longest rule that matches a prefix of
+/// the text is applied. sentencepiece models store the rules in compiled
+/// form, as the precompiled character map of the normalizer
+/// specification. The rules are usually maintained in the
+/// `normalization_rule_tsv` format, which can be read and written using
+/// [`read_tsv`](Self::read_tsv) and [`write_tsv`](Self::write_tsv).
+///
+/// For example, the full-width folding of a model can be disabled by
+/// removing the rules for full-width Latin letters:
+///
+/// ```
+/// use sentencepiece::{ModelEditor, NormalizationRules, SentencePieceProcessor};
+///
+/// let spp = SentencePieceProcessor::open("testdata/toy.model").unwrap();
+/// let spec = spp.model_proto().normalizer_spec.unwrap();
+/// let mut rules = NormalizationRules::from_normalizer_spec(&spec).unwrap();
+/// for c in ('Ａ'..='Ｚ').chain('ａ'..='ｚ') {
+///     rules.remove(&c.to_string());
+/// }
+///
+/// let mut editor = ModelEditor::new(&spp);
+/// editor.set_normalization_rules(&rules).unwrap();
+/// let spp = editor.build().unwrap();
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct NormalizationRules {
+    rules: BTreeMap<String, String>,
+}
+
+impl NormalizationRules {
+    /// Construct an empty set of rules.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decompile the rules of a precompiled character map.
+    pub fn from_precompiled_charsmap(charsmap: &[u8]) -> Result<Self, SentencePieceError> {
+        let mut rules = NormalizationRules::new();
+
+        // An empty character map does not normalize.
+        if charsmap.is_empty() {
+            return Ok(rules);
+        }
+
+        let (units, normalized) = decode_charsmap(charsmap)?;
+        if !units.is_empty() {
+            decompile_node(
+                &units,
+                normalized,
+                0,
+                &mut Vec::new(),
+                &mut 0,
+                &mut rules.rules,
+            )?;
+        }
+
+        Ok(rules)
+    }
+
+    /// Decompile the rules of a normalizer specification.
+    pub fn from_normalizer_spec(
+        normalizer_spec: &NormalizerSpec,
+    ) -> Result<Self, SentencePieceError> {
+        Self::from_precompiled_charsmap(normalizer_spec.precompiled_charsmap())
+    }
+
+    /// Get the normalized form of a character sequence.
+    pub fn get(&self, source: &str) -> Option<&str> {
+        self.rules.get(source).map(String::as_str)
+    }
+
+    /// Add a rule, replacing the rule with the same source.
+    ///
+    /// An empty target removes the source during normalization.
+    pub fn insert(&mut self, source: impl Into<String>, target: impl Into<String>) {
+        self.rules.insert(source.into(), target.into());
+    }
+
+    /// Check whether there are no rules.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Get an iterator over the rules as pairs of source and target.
+    ///
+    /// The rules are ordered by their source.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            inner: self.rules.iter(),
+        }
+    }
+
+    /// Get the number of rules.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Read rules in `normalization_rule_tsv` format.
+    ///
+    /// Each line contains the source and target character sequences,
+    /// separated by a tab. A character sequence is a space-separated list
+    /// of hexadecimal code points, optionally prefixed by `U+`. Further
+    /// fields are ignored, they typically contain a readable form of the
+    /// rule. A line without a target is a rule that removes the source.
+    ///
+    /// ```
+    /// use sentencepiece::NormalizationRules;
+    ///
+    /// let rules = NormalizationRules::read_tsv("FF21\t41\nU+2160\t49\t# Ⅰ => I\n".as_bytes()).unwrap();
+    /// assert_eq!(rules.get("Ａ"), Some("A"));
+    /// assert_eq!(rules.get("Ⅰ"), Some("I"));
+    /// ```
+    pub fn read_tsv(read: impl BufRead) -> io::Result<Self> {
+        let mut rules = NormalizationRules::new();
+        for line in read.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split('\t');
+            let source = parse_code_points(fields.next().unwrap_or_default(), &line)?;
+            let target = parse_code_points(fields.next().unwrap_or_default(), &line)?;
+            if source.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Normalization rule without source: {}", line),
+                ));
+            }
+
+            rules.insert(source, target);
+        }
+
+        Ok(rules)
+    }
+
+    /// Remove the rule for a source, returning its target.
+    pub fn remove(&mut self, source: &str) -> Option<String> {
+        self.rules.remove(source)
+    }
+
+    /// Compile the rules into a precompiled character map.
+    ///
+    /// The character map is in the darts-clone double array format that
+    /// sentencepiece uses. An empty set of rules is compiled into an empty
+    /// character map, which does not normalize.
+    pub fn to_precompiled_charsmap(&self) -> Result<Vec<u8>, SentencePieceError> {
+        if self.rules.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let invalid = |message: String| Err(SentencePieceError::InvalidNormalizationRules(message));
+
+        for (source, target) in &self.rules {
+            if source.is_empty() {
+                return invalid("rule without source".to_string());
+            }
+            if source.contains('\0') || target.contains('\0') {
+                return invalid(format!("rule contains nul character: {:?}", source));
+            }
+
+            let matches = source
+                .char_indices()
+                .skip(1)
+                .map(|(idx, _)| &source[..idx])
+                .chain(Some(source.as_str()))
+                .filter(|prefix| self.rules.contains_key(*prefix))
+                .count();
+            if matches >= MAX_PREFIX_MATCHES {
+                return invalid(format!("too many rules match a prefix of: {:?}", source));
+            }
+        }
+
+        // Store every distinct target once, as nul-terminated strings.
+        let mut target_offsets = BTreeMap::new();
+        for target in self.rules.values() {
+            target_offsets.insert(target.as_str(), 0);
+        }
+        let mut normalized = Vec::new();
+        for (target, offset) in target_offsets.iter_mut() {
+            *offset = normalized.len() as u32;
+            normalized.extend_from_slice(target.as_bytes());
+            normalized.push(0);
+        }
+
+        let units = DoubleArrayBuilder::build(
+            self.rules
+                .iter()
+                .map(|(source, target)| (source.as_bytes(), target_offsets[target.as_str()])),
+        )?;
+
+        let mut charsmap = Vec::with_capacity(4 + units.len() * 4 + normalized.len());
+        charsmap.extend_from_slice(&((units.len() * 4) as u32).to_le_bytes());
+        for unit in units {
+            charsmap.extend_from_slice(&unit.to_le_bytes());
+        }
+        charsmap.extend_from_slice(&normalized);
+
+        Ok(charsmap)
+    }
+
+    /// Write the rules in `normalization_rule_tsv` format.
+    ///
+    /// Every rule is written with a third field that contains the rule in
+    /// readable form, such as `# Ａ => A` for the rule `FF21` to `41`.
+    pub fn write_tsv(&self, mut write: impl Write) -> io::Result<()> {
+        let hex = |s: &str| {
+            s.chars()
+                .map(|c| format!("{:X}", c as u32))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let readable = |s: &str| {
+            s.chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect::<String>()
+        };
+
+        for (source, target) in &self.rules {
+            writeln!(
+                write,
+                "{}\t{}\t# {} => {}",
+                hex(source),
+                hex(target),
+                readable(source),
+                readable(target)
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a NormalizationRules {
+    type Item = (&'a str, &'a str);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over normalization rules.
+pub struct Iter<'a> {
+    inner: btree_map::Iter<'a, String, String>,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|(source, target)| (source.as_str(), target.as_str()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+fn parse_code_points(field: &str, line: &str) -> io::Result<String> {
+    field
+        .split(' ')
+        .filter(|code_point| !code_point.is_empty())
+        .map(|code_point| {
+            let hex = code_point.strip_prefix("U+").unwrap_or(code_point);
+            u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid code point {} in rule: {}", code_point, line),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Split a precompiled character map into the double array units and the
+/// normalized strings.
+fn decode_charsmap(charsmap: &[u8]) -> Result<(Vec<u32>, &[u8]), SentencePieceError> {
+    let invalid = |message: &str| SentencePieceError::InvalidCharsMap(message.to_string());
+
+    if charsmap.len() < 4 {
+        return Err(invalid("character map is truncated"));
+    }
+    let trie_len = u32::from_le_bytes(charsmap[..4].try_into().unwrap()) as usize;
+    let data = &charsmap[4..];
+    if trie_len >= data.len() {
+        return Err(invalid("trie size exceeds the character map size"));
+    }
+
+    let chunks = data[..trie_len].chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return Err(invalid("trie size is not a multiple of the unit size"));
+    }
+    let units = chunks
+        .map(|unit| u32::from_le_bytes(unit.try_into().unwrap()))
+        .collect();
+
+    Ok((units, &data[trie_len..]))
+}
+
+/// Recursively collect the rules of a double array node, in the same way
+/// as sentencepiece's `Builder::DecompileCharsMap`.
+///
+/// `visited` counts the visited nodes, every node adds at most one rule.
+fn decompile_node(
+    units: &[u32],
+    normalized: &[u8],
+    node_pos: usize,
+    source: &mut Vec<u8>,
+    visited: &mut usize,
+    rules: &mut BTreeMap<String, String>,
+) -> Result<(), SentencePieceError> {
+    let invalid = |message: String| SentencePieceError::InvalidCharsMap(message);
+
+    if source.len() > MAX_SOURCE_LEN {
+        return Err(invalid("rule source is too long".to_string()));
+    }
+
+    *visited += 1;
+    if *visited > MAX_DECOMPILE_NODES {
+        return Err(invalid("character map has too many nodes".to_string()));
+    }
+
+    let offset = unit::offset(units[node_pos]);
+    for label in 0..=255u8 {
+        let child_pos = node_pos ^ offset ^ label as usize;
+        let child = match units.get(child_pos) {
+            Some(&child) if unit::label(child) == label as u32 => child,
+            _ => continue,
+        };
+
+        source.push(label);
+
+        if unit::has_leaf(child) {
+            let value = units
+                .get(child_pos ^ unit::offset(child))
+                .map(|&leaf| unit::value(leaf) as usize)
+                .ok_or_else(|| invalid("leaf is out of bounds".to_string()))?;
+            let target = normalized
+                .get(value..)
+                .and_then(|target| target.split(|&b| b == 0).next())
+                .ok_or_else(|| invalid("normalized string is out of bounds".to_string()))?;
+
+            let source = String::from_utf8(source.clone())
+                .map_err(|_| invalid("rule source is not UTF-8".to_string()))?;
+            let target = String::from_utf8(target.to_vec())
+                .map_err(|_| invalid(format!("target of {:?} is not UTF-8", source)))?;
+            rules.insert(source, target);
+        }
+
+        decompile_node(units, normalized, child_pos, source, visited, rules)?;
+
+        source.pop();
+    }
+
+    Ok(())
+}
+
+/// Accessors for darts-clone double array units.
+pub(crate) mod unit {
+    const HAS_LEAF: u32 = 1 << 8;
+    const IS_LEAF: u32 = 1 << 31;
+    const EXTENDED_OFFSET: u32 = 1 << 9;
+
+    pub fn has_leaf(unit: u32) -> bool {
+        unit & HAS_LEAF != 0
+    }
+
+    /// Get the label, leaf units never match a label.
+    pub fn label(unit: u32) -> u32 {
+        unit & (IS_LEAF | 0xff)
+    }
+
+    pub fn offset(unit: u32) -> usize {
+        ((unit >> 10) << ((unit & EXTENDED_OFFSET) >> 6)) as usize
+    }
+
+    pub fn value(unit: u32) -> u32 {
+        unit & !IS_LEAF
+    }
+
+    /// Construct an inner unit. Returns `None` if the offset cannot be
+    /// encoded.
+    pub fn inner(label: u8, has_leaf: bool, offset: usize) -> Option<u32> {
+        let offset = if offset < 1 << 21 {
+            (offset as u32) << 10
+        } else if offset < 1 << 29 && offset & 0xff == 0 {
+            ((offset as u32) << 2) | EXTENDED_OFFSET
+        } else {
+            return None;
+        };
+
+        let has_leaf = if has_leaf { HAS_LEAF } else { 0 };
+
+        Some(offset | has_leaf | label as u32)
+    }
+
+    pub fn leaf(value: u32) -> u32 {
+        value | IS_LEAF
+    }
+}
+
+/// Number of trailing blocks in which free units are searched, as in
+/// darts-clone. Free units in earlier blocks remain unused.
+const NUM_OPEN_BLOCKS: usize = 16;
+
+/// Node of the trie that is built from the keys.
+#[derive(Default)]
+struct TrieNode {
+    children: Vec<(u8, usize)>,
+    value: Option<u32>,
+}
+
+/// State of the minimized trie. States with the same value and the same
+/// transitions are merged, so that common suffixes are stored once.
+#[derive(Clone, Eq, Hash, PartialEq)]
+struct State {
+    children: Vec<(u8, usize)>,
+    value: Option<u32>,
+}
+
+/// Build the minimized trie of the keys, returning the states and the
+/// index of the root state.
+fn build_states<'a>(keys: impl Iterator<Item = (&'a [u8], u32)>) -> (Vec<State>, usize) {
+    let mut nodes = vec![TrieNode::default()];
+    for (key, value) in keys {
+        let mut node = 0;
+        for &label in key {
+            node = match nodes[node].children.iter().find(|(l, _)| *l == label) {
+                Some(&(_, child)) => child,
+                None => {
+                    nodes.push(TrieNode::default());
+                    let child = nodes.len() - 1;
+                    nodes[node].children.push((label, child));
+                    child
+                }
+            };
+        }
+        nodes[node].value = Some(value);
+    }
+
+    // Children are always added after their parents, so visiting the
+    // nodes in reverse order merges the children before their parents.
+    let mut states = Vec::new();
+    let mut state_ids = HashMap::new();
+    let mut node_states = vec![0; nodes.len()];
+    for (node_id, node) in nodes.iter().enumerate().rev() {
+        let state = State {
+            children: node
+                .children
+                .iter()
+                .map(|&(label, child)| (label, node_states[child]))
+                .collect(),
+            value: node.value,
+        };
+        node_states[node_id] = *state_ids.entry(state.clone()).or_insert_with(|| {
+            states.push(state);
+            states.len() - 1
+        });
+    }
+
+    (states, node_states[0])
+}
+
+/// Builder of darts-clone double arrays.
+///
+/// Every node stores the offset to the base of its children. A child is
+/// found at `node_pos ^ offset ^ label` and the value of a node at
+/// `node_pos ^ offset`, in the child with label 0. Nodes of the same
+/// state share their children, all other bases are unique, so that unused
+/// units can be labeled such that they never match.
+pub(crate) struct DoubleArrayBuilder {
+    units: Vec<u32>,
+    occupied: Vec<bool>,
+    used_bases: Vec<bool>,
+
+    /// Circular list of the free units in open blocks.
+    next_free: Vec<usize>,
+    prev_free: Vec<usize>,
+    free_head: Option<usize>,
+
+    closed_blocks: usize,
+}
+
+impl DoubleArrayBuilder {
+    pub(crate) fn build<'a>(
+        keys: impl Iterator<Item = (&'a [u8], u32)>,
+    ) -> Result<Vec<u32>, SentencePieceError> {
+        let (states, root) = build_states(keys);
+
+        let mut builder = DoubleArrayBuilder {
+            units: Vec::new(),
+            occupied: Vec::new(),
+            used_bases: Vec::new(),
+            next_free: Vec::new(),
+            prev_free: Vec::new(),
+            free_head: None,
+            closed_blocks: 0,
+        };
+        builder.add_block();
+        builder.occupy(0, 0);
+        builder.used_bases[0] = true;
+
+        let mut state_bases = vec![None; states.len()];
+        let mut stack = vec![(root, 0)];
+        while let Some((state_id, node_pos)) = stack.pop() {
+            let state = &states[state_id];
+            let label = (builder.units[node_pos] & 0xff) as u8;
+            let has_leaf = state.value.is_some();
+
+            // Reuse the children of a state that was already placed.
+            if let Some(unit) = state_bases[state_id]
+                .and_then(|base: usize| unit::inner(label, has_leaf, node_pos ^ base))
+            {
+                builder.units[node_pos] = unit;
+                continue;
+            }
+
+            let mut labels = Vec::with_capacity(state.children.len() + 1);
+            if has_leaf {
+                labels.push(0);
+            }
+            labels.extend(state.children.iter().map(|&(label, _)| label));
+
+            if labels.is_empty() {
+                continue;
+            }
+
+            let base = builder.find_base(node_pos, &labels)?;
+            while base >= builder.units.len() {
+                builder.add_block();
+            }
+            builder.units[node_pos] =
+                unit::inner(label, has_leaf, node_pos ^ base).expect("Offset must be encodable");
+            builder.used_bases[base] = true;
+            state_bases[state_id] = Some(base);
+
+            if let Some(value) = state.value {
+                builder.occupy(base, unit::leaf(value));
+            }
+            for &(label, child) in &state.children {
+                builder.occupy(base ^ label as usize, label as u32);
+                stack.push((child, base ^ label as usize));
+            }
+        }
+
+        Ok(builder.finish())
+    }
+
+    /// Find a base for the children of a node.
+    fn find_base(&self, node_pos: usize, labels: &[u8]) -> Result<usize, SentencePieceError> {
+        if let Some(head) = self.free_head {
+            let mut pos = head;
+            loop {
+                // Children are in the same block as the base, which is open.
+                let base = pos ^ labels[0] as usize;
+                if !self.used_bases[base]
+                    && unit::inner(0, false, node_pos ^ base).is_some()
+                    && labels[1..]
+                        .iter()
+                        .all(|&label| !self.occupied[base ^ label as usize])
+                {
+                    return Ok(base);
+                }
+
+                pos = self.next_free[pos];
+                if pos == head {
+                    break;
+                }
+            }
+        }
+
+        // Use a new block. The lower bits of the offset are zero, so that
+        // the offset can be encoded when it is large.
+        let base = self.units.len() | (node_pos & 0xff);
+        if node_pos ^ base >= 1 << 29 {
+            return Err(SentencePieceError::InvalidNormalizationRules(
+                "too many rules".to_string(),
+            ));
+        }
+
+        Ok(base)
+    }
+
+    /// Add a block of 256 units and close the oldest block when there are
+    /// too many open blocks.
+    fn add_block(&mut self) {
+        let begin = self.units.len();
+        let end = begin + 256;
+        self.units.resize(end, 0);
+        self.occupied.resize(end, false);
+        self.used_bases.resize(end, false);
+        self.next_free.resize(end, 0);
+        self.prev_free.resize(end, 0);
+
+        for pos in begin..end {
+            self.link_free(pos);
+        }
+
+        if end / 256 - self.closed_blocks > NUM_OPEN_BLOCKS {
+            let begin = self.closed_blocks * 256;
+            for pos in begin..begin + 256 {
+                if !self.occupied[pos] {
+                    self.unlink_free(pos);
+                }
+            }
+            self.closed_blocks += 1;
+        }
+    }
+
+    fn link_free(&mut self, pos: usize) {
+        match self.free_head {
+            Some(head) => {
+                let tail = self.prev_free[head];
+                self.next_free[tail] = pos;
+                self.prev_free[pos] = tail;
+                self.next_free[pos] = head;
+                self.prev_free[head] = pos;
+            }
+            None => {
+                self.next_free[pos] = pos;
+                self.prev_free[pos] = pos;
+                self.free_head = Some(pos);
+            }
+        }
+    }
+
+    fn unlink_free(&mut self, pos: usize) {
+        let next = self.next_free[pos];
+        if next == pos {
+            self.free_head = None;
+        } else {
+            let prev = self.prev_free[pos];
+            self.next_free[prev] = next;
+            self.prev_free[next] = prev;
+            if self.free_head == Some(pos) {
+                self.free_head = Some(next);
+            }
+        }
+    }
+
+    fn occupy(&mut self, pos: usize, unit: u32) {
+        self.occupied[pos] = true;
+        self.units[pos] = unit;
+        self.unlink_free(pos);
+    }
+
+    /// Label unused units such that they do not match during traversal.
+    fn finish(mut self) -> Vec<u32> {
+        for block in 0..self.units.len() / 256 {
+            let begin = block * 256;
+            let end = begin + 256;
+
+            // A child with label l is found at base ^ l, so an unused unit
+            // at pos with label pos ^ unused_base can only be reached from
+            // unused_base, which is not the base of any node.
+            let unused_base = (begin..end).find(|&pos| !self.used_bases[pos]).unwrap_or(0);
+            for pos in begin..end {
+                if !self.occupied[pos] {
+                    self.units[pos] = ((pos ^ unused_base) & 0xff) as u32;
+                }
+            }
+        }
+
+        self.units
+    }
+}
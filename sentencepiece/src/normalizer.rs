@@ -0,0 +1,89 @@
+use crate::sentencepiece_model::{
+    model_proto, trainer_spec, ModelProto, NormalizerSpec, TrainerSpec,
+};
+use crate::{PieceType, SentencePieceError, SentencePieceProcessor};
+
+/// Normalizer of a sentencepiece model.
+///
+/// The normalizer applies the normalization that sentencepiece performs
+/// before segmentation, such as NFKC normalization and the replacement of
+/// whitespace by the meta symbol (U+2581).
+///
+/// ```
+/// use sentencepiece::{Normalizer, SentencePieceProcessor};
+///
+/// let spp = SentencePieceProcessor::open("testdata/toy.model").unwrap();
+/// let normalizer = Normalizer::new(&spp);
+/// let (normalized, _) = normalizer.normalize("I saw  a girl").unwrap();
+/// assert_eq!(normalized, "▁I▁saw▁a▁girl");
+/// ```
+#[derive(Debug)]
+pub struct Normalizer<'a> {
+    inner: Processor<'a>,
+}
+
+/// Processor that performs the normalization.
+#[derive(Debug)]
+enum Processor<'a> {
+    Borrowed(&'a SentencePieceProcessor),
+    Owned(SentencePieceProcessor),
+}
+
+impl<'a> Normalizer<'a> {
+    /// Construct the normalizer of a processor.
+    ///
+    /// The normalizer normalizes text in the same way as the processor,
+    /// including the handling of user-defined pieces, which are not
+    /// normalized.
+    pub fn new(spp: &'a SentencePieceProcessor) -> Self {
+        Normalizer {
+            inner: Processor::Borrowed(spp),
+        }
+    }
+
+    /// Construct a normalizer from a normalizer specification.
+    pub fn from_normalizer_spec(
+        normalizer_spec: NormalizerSpec,
+    ) -> Result<Normalizer<'static>, SentencePieceError> {
+        // sentencepiece can only construct a normalizer as part of a model,
+        // so construct a character model with just the unknown piece.
+        let model = ModelProto {
+            pieces: vec![model_proto::SentencePiece {
+                piece: Some("<unk>".to_string()),
+                score: Some(0.),
+                r#type: Some(PieceType::Unknown as i32),
+            }],
+            trainer_spec: Some(TrainerSpec {
+                model_type: Some(trainer_spec::ModelType::Char as i32),
+                vocab_size: Some(1),
+                unk_id: Some(0),
+                bos_id: Some(-1),
+                eos_id: Some(-1),
+                ..Default::default()
+            }),
+            normalizer_spec: Some(normalizer_spec),
+            ..Default::default()
+        };
+
+        Ok(Normalizer {
+            inner: Processor::Owned(SentencePieceProcessor::from_serialized_proto(
+                &prost::Message::encode_to_vec(&model),
+            )?),
+        })
+    }
+
+    /// Normalize a text.
+    ///
+    /// Returns the normalized text and its alignment to the original text.
+    /// The alignment contains for every byte of the normalized text the
+    /// byte offset of the original character that it was derived from,
+    /// followed by the length of the original text.
+    pub fn normalize(&self, text: &str) -> Result<(String, Vec<usize>), SentencePieceError> {
+        let spp = match &self.inner {
+            Processor::Borrowed(spp) => spp,
+            Processor::Owned(spp) => spp,
+        };
+
+        spp.normalize_with_alignment(text)
+    }
+}
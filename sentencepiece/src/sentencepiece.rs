@@ -1,5 +1,12 @@
 use prost_derive::Message;
 
+#[derive(Clone, PartialEq, Message)]
+pub struct NBestSentencePieceText {
+    /// The n-best segmentations.
+    #[prost(message, repeated, tag = "1")]
+    pub nbests: Vec<SentencePieceText>,
+}
+
 #[derive(Clone, PartialEq, Message)]
 pub struct SentencePieceText {
     /// User input or postprocessed text.
@@ -0,0 +1,561 @@
+//! Protocol buffer types of sentencepiece models.
+//!
+//! These types correspond to the messages in `sentencepiece_model.proto`
+//! and can be used to inspect a model that was obtained through
+//! [`SentencePieceProcessor::model_proto`](crate::SentencePieceProcessor::model_proto).
+
+use prost_derive::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::SentencePieceError;
+
+/// Define a message type whose unset fields are omitted in JSON.
+///
+/// Optional fields that are `None` and repeated fields that are empty are
+/// not serialized. Combined with `#[serde(default)]` on the message, this
+/// results in the same message after deserialization.
+macro_rules! json_message {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                pub $field:ident: $ty:ty,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        pub struct $name {
+            $(
+                $(#[$field_attr])*
+                #[serde(skip_serializing_if = "crate::sentencepiece_model::Unset::is_unset")]
+                pub $field: $ty,
+            )*
+        }
+    };
+}
+
+/// Check whether a field is unset.
+trait Unset {
+    fn is_unset(&self) -> bool;
+}
+
+impl<T> Unset for Option<T> {
+    fn is_unset(&self) -> bool {
+        self.is_none()
+    }
+}
+
+impl<T> Unset for Vec<T> {
+    fn is_unset(&self) -> bool {
+        self.is_empty()
+    }
+}
+
+json_message! {
+    /// Trainer specification, stored in the model.
+    #[derive(Clone, Deserialize, PartialEq, Message, Serialize)]
+    #[serde(default)]
+    pub struct TrainerSpec {
+        /// Input corpus files.
+        #[prost(string, repeated, tag = "1")]
+        pub input: Vec<String>,
+
+        /// Input corpus format.
+        #[prost(string, optional, tag = "7")]
+        pub input_format: Option<String>,
+
+        /// Output model file prefix.
+        #[prost(string, optional, tag = "2")]
+        pub model_prefix: Option<String>,
+
+        /// Model type.
+        #[prost(
+            enumeration = "trainer_spec::ModelType",
+            optional,
+            tag = "3",
+            default = "Unigram"
+        )]
+        #[serde(with = "model_type_name")]
+        pub model_type: Option<i32>,
+
+        /// Vocabulary size.
+        #[prost(int32, optional, tag = "4", default = "8000")]
+        pub vocab_size: Option<i32>,
+
+        /// List of the languages this model can accept.
+        #[prost(string, repeated, tag = "5")]
+        pub accept_language: Vec<String>,
+
+        /// Size of self-test samples, which are encoded in the model file.
+        #[prost(int32, optional, tag = "6", default = "0")]
+        pub self_test_sample_size: Option<i32>,
+
+        /// Whether to use differential privacy when training.
+        #[prost(bool, optional, tag = "50", default = "false")]
+        pub enable_differential_privacy: Option<bool>,
+
+        /// Noise level of the differential privacy mechanism.
+        #[prost(float, optional, tag = "51", default = "0")]
+        #[serde(with = "float_name")]
+        pub differential_privacy_noise_level: Option<f32>,
+
+        /// Clipping threshold of the differential privacy mechanism.
+        #[prost(uint64, optional, tag = "52", default = "0")]
+        pub differential_privacy_clipping_threshold: Option<u64>,
+
+        /// Amount of characters covered by the model.
+        #[prost(float, optional, tag = "10", default = "0.9995")]
+        #[serde(with = "float_name")]
+        pub character_coverage: Option<f32>,
+
+        /// Maximum size of sentences the trainer loads from the input.
+        #[prost(uint64, optional, tag = "11", default = "0")]
+        pub input_sentence_size: Option<u64>,
+
+        /// Randomly sample input sentences when `input_sentence_size` is set.
+        #[prost(bool, optional, tag = "19", default = "true")]
+        pub shuffle_input_sentence: Option<bool>,
+
+        /// Deprecated.
+        #[prost(int32, optional, tag = "12")]
+        pub mining_sentence_size: Option<i32>,
+
+        /// Deprecated.
+        #[prost(int32, optional, tag = "13")]
+        pub training_sentence_size: Option<i32>,
+
+        /// Size of the seed sentence pieces (unigram).
+        #[prost(int32, optional, tag = "14", default = "1000000")]
+        pub seed_sentencepiece_size: Option<i32>,
+
+        /// Fraction of pieces that is kept in each EM iteration (unigram).
+        #[prost(float, optional, tag = "15", default = "0.75")]
+        #[serde(with = "float_name")]
+        pub shrinking_factor: Option<f32>,
+
+        /// Maximum length of a sentence in bytes.
+        #[prost(int32, optional, tag = "18", default = "4192")]
+        pub max_sentence_length: Option<i32>,
+
+        /// Number of threads used for training.
+        #[prost(int32, optional, tag = "16", default = "16")]
+        pub num_threads: Option<i32>,
+
+        /// Number of EM sub-iterations (unigram).
+        #[prost(int32, optional, tag = "17", default = "2")]
+        pub num_sub_iterations: Option<i32>,
+
+        /// Maximum length of a sentence piece in characters.
+        #[prost(int32, optional, tag = "20", default = "16")]
+        pub max_sentencepiece_length: Option<i32>,
+
+        /// Split pieces at Unicode script boundaries.
+        #[prost(bool, optional, tag = "21", default = "true")]
+        pub split_by_unicode_script: Option<bool>,
+
+        /// Split pieces at number boundaries.
+        #[prost(bool, optional, tag = "23", default = "true")]
+        pub split_by_number: Option<bool>,
+
+        /// Split pieces at whitespace.
+        #[prost(bool, optional, tag = "22", default = "true")]
+        pub split_by_whitespace: Option<bool>,
+
+        /// Attach the whitespace marker as a suffix rather than a prefix.
+        #[prost(bool, optional, tag = "24", default = "false")]
+        pub treat_whitespace_as_suffix: Option<bool>,
+
+        /// Allow pieces that only consist of whitespace.
+        #[prost(bool, optional, tag = "26", default = "false")]
+        pub allow_whitespace_only_pieces: Option<bool>,
+
+        /// Split all digits into separate pieces.
+        #[prost(bool, optional, tag = "25", default = "false")]
+        pub split_digits: Option<bool>,
+
+        /// Delimiter that is used to pre-tokenize the training data.
+        #[prost(string, optional, tag = "53", default = "")]
+        pub pretokenization_delimiter: Option<String>,
+
+        /// Control symbols, which are never extracted from the input.
+        #[prost(string, repeated, tag = "30")]
+        pub control_symbols: Vec<String>,
+
+        /// User-defined symbols, which are always segmented as one piece.
+        #[prost(string, repeated, tag = "31")]
+        pub user_defined_symbols: Vec<String>,
+
+        /// Characters that are always added to the vocabulary.
+        #[prost(string, optional, tag = "36")]
+        pub required_chars: Option<String>,
+
+        /// Decompose unknown pieces into UTF-8 byte pieces.
+        #[prost(bool, optional, tag = "35", default = "false")]
+        pub byte_fallback: Option<bool>,
+
+        /// Write piece scores to the vocabulary file.
+        #[prost(bool, optional, tag = "32", default = "true")]
+        pub vocabulary_output_piece_score: Option<bool>,
+
+        /// Treat the vocabulary size as a hard limit.
+        #[prost(bool, optional, tag = "33", default = "true")]
+        pub hard_vocab_limit: Option<bool>,
+
+        /// Use all symbols as the vocabulary (word/char models).
+        #[prost(bool, optional, tag = "34", default = "false")]
+        pub use_all_vocab: Option<bool>,
+
+        /// Identifier of the unknown piece.
+        #[prost(int32, optional, tag = "40", default = "0")]
+        pub unk_id: Option<i32>,
+
+        /// Identifier of the begin of sentence piece, -1 when disabled.
+        #[prost(int32, optional, tag = "41", default = "1")]
+        pub bos_id: Option<i32>,
+
+        /// Identifier of the end of sentence piece, -1 when disabled.
+        #[prost(int32, optional, tag = "42", default = "2")]
+        pub eos_id: Option<i32>,
+
+        /// Identifier of the padding piece, -1 when disabled.
+        #[prost(int32, optional, tag = "43", default = "-1")]
+        pub pad_id: Option<i32>,
+
+        /// Unknown piece.
+        #[prost(string, optional, tag = "45", default = "<unk>")]
+        pub unk_piece: Option<String>,
+
+        /// Begin of sentence piece.
+        #[prost(string, optional, tag = "46", default = "<s>")]
+        pub bos_piece: Option<String>,
+
+        /// End of sentence piece.
+        #[prost(string, optional, tag = "47", default = "</s>")]
+        pub eos_piece: Option<String>,
+
+        /// Padding piece.
+        #[prost(string, optional, tag = "48", default = "<pad>")]
+        pub pad_piece: Option<String>,
+
+        /// Surface of the unknown piece when decoding.
+        #[prost(string, optional, tag = "44", default = " \u{2047} ")]
+        pub unk_surface: Option<String>,
+
+        /// Use a trainer that handles extremely large corpora.
+        #[prost(bool, optional, tag = "49", default = "false")]
+        pub train_extremely_large_corpus: Option<bool>,
+
+        /// File with seed sentence pieces (unigram).
+        #[prost(string, optional, tag = "54", default = "")]
+        pub seed_sentencepieces_file: Option<String>,
+    }
+}
+
+pub mod trainer_spec {
+    use prost_derive::Enumeration;
+
+    /// Model type.
+    #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Enumeration)]
+    #[repr(i32)]
+    pub enum ModelType {
+        Unigram = 1,
+        Bpe = 2,
+        Word = 3,
+        Char = 4,
+    }
+}
+
+json_message! {
+    /// Normalizer specification.
+    #[derive(Clone, Deserialize, PartialEq, Message, Serialize)]
+    #[serde(default)]
+    pub struct NormalizerSpec {
+        /// Name of the normalization rule.
+        #[prost(string, optional, tag = "1")]
+        pub name: Option<String>,
+
+        /// Pre-compiled normalization rule, in the darts-clone double array
+        /// format.
+        #[prost(bytes = "vec", optional, tag = "2")]
+        #[serde(with = "base64_bytes")]
+        pub precompiled_charsmap: Option<Vec<u8>>,
+
+        /// Add a dummy whitespace at the beginning of the text.
+        #[prost(bool, optional, tag = "3", default = "true")]
+        pub add_dummy_prefix: Option<bool>,
+
+        /// Remove leading, trailing and duplicate internal whitespace.
+        #[prost(bool, optional, tag = "4", default = "true")]
+        pub remove_extra_whitespaces: Option<bool>,
+
+        /// Replace whitespace by the meta symbol (U+2581).
+        #[prost(bool, optional, tag = "5", default = "true")]
+        pub escape_whitespaces: Option<bool>,
+
+        /// Custom normalization rule file in TSV format.
+        #[prost(string, optional, tag = "6")]
+        pub normalization_rule_tsv: Option<String>,
+    }
+}
+
+json_message! {
+    /// Self-test data, used to verify a model after loading.
+    #[derive(Clone, Deserialize, PartialEq, Message, Serialize)]
+    #[serde(default)]
+    pub struct SelfTestData {
+        #[prost(message, repeated, tag = "1")]
+        pub samples: Vec<self_test_data::Sample>,
+    }
+}
+
+pub mod self_test_data {
+    use prost_derive::Message;
+    use serde::{Deserialize, Serialize};
+
+    json_message! {
+        #[derive(Clone, Deserialize, PartialEq, Message, Serialize)]
+        #[serde(default)]
+        pub struct Sample {
+            #[prost(string, optional, tag = "1")]
+            pub input: Option<String>,
+
+            #[prost(string, optional, tag = "2")]
+            pub expected: Option<String>,
+        }
+    }
+}
+
+json_message! {
+    /// Sentencepiece model.
+    #[derive(Clone, Deserialize, PartialEq, Message, Serialize)]
+    #[serde(default)]
+    pub struct ModelProto {
+        /// Sentence pieces with scores.
+        #[prost(message, repeated, tag = "1")]
+        pub pieces: Vec<model_proto::SentencePiece>,
+
+        /// Spec used to generate this model file.
+        #[prost(message, optional, tag = "2")]
+        pub trainer_spec: Option<TrainerSpec>,
+
+        /// Spec for text normalization.
+        #[prost(message, optional, tag = "3")]
+        pub normalizer_spec: Option<NormalizerSpec>,
+
+        /// Stores sample input and its expected segmentation to verify the
+        /// model.
+        #[prost(message, optional, tag = "4")]
+        pub self_test_data: Option<SelfTestData>,
+
+        /// Spec for text de-normalization.
+        #[prost(message, optional, tag = "5")]
+        pub denormalizer_spec: Option<NormalizerSpec>,
+    }
+}
+
+pub mod model_proto {
+    use prost_derive::Message;
+    use serde::{Deserialize, Serialize};
+
+    use super::{float_name, piece_type_name};
+
+    json_message! {
+        #[derive(Clone, Deserialize, PartialEq, Message, Serialize)]
+        #[serde(default)]
+        pub struct SentencePiece {
+            /// The piece, must be non-empty.
+            #[prost(string, optional, tag = "1")]
+            pub piece: Option<String>,
+
+            /// Score of the piece.
+            #[prost(float, optional, tag = "2")]
+            #[serde(with = "float_name")]
+            pub score: Option<f32>,
+
+            /// Type of the piece.
+            #[prost(
+                enumeration = "sentence_piece::Type",
+                optional,
+                tag = "3",
+                default = "Normal"
+            )]
+            #[serde(with = "piece_type_name")]
+            pub r#type: Option<i32>,
+        }
+    }
+
+    pub mod sentence_piece {
+        use prost_derive::Enumeration;
+
+        /// Type of a sentence piece.
+        #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Enumeration)]
+        #[repr(i32)]
+        pub enum Type {
+            Normal = 1,
+            Unknown = 2,
+            Control = 3,
+            UserDefined = 4,
+            Byte = 6,
+            Unused = 5,
+        }
+    }
+}
+
+/// Enumeration value in JSON, either as its name or its number.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum EnumValue {
+    Name(String),
+    Number(i32),
+}
+
+/// Serialize enumeration values by their names in the protobuf definition.
+macro_rules! enum_name_serde {
+    ($module:ident, $($name:literal => $value:path),* $(,)?) => {
+        pub(crate) mod $module {
+            use serde::de::Error;
+            use serde::{Deserialize, Deserializer, Serializer};
+
+            use super::EnumValue;
+
+            pub fn serialize<S>(value: &Option<i32>, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                match value {
+                    $(Some(v) if *v == $value as i32 => serializer.serialize_str($name),)*
+                    // Unknown values are serialized as numbers to retain them.
+                    Some(v) => serializer.serialize_i32(*v),
+                    None => serializer.serialize_none(),
+                }
+            }
+
+            pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                match Option::<EnumValue>::deserialize(deserializer)? {
+                    $(Some(EnumValue::Name(name)) if name == $name => Ok(Some($value as i32)),)*
+                    Some(EnumValue::Name(name)) => {
+                        Err(D::Error::custom(format!("unknown enumeration value: {}", name)))
+                    }
+                    Some(EnumValue::Number(value)) => Ok(Some(value)),
+                    None => Ok(None),
+                }
+            }
+        }
+    };
+}
+
+enum_name_serde!(model_type_name,
+    "UNIGRAM" => super::trainer_spec::ModelType::Unigram,
+    "BPE" => super::trainer_spec::ModelType::Bpe,
+    "WORD" => super::trainer_spec::ModelType::Word,
+    "CHAR" => super::trainer_spec::ModelType::Char,
+);
+
+enum_name_serde!(piece_type_name,
+    "NORMAL" => super::model_proto::sentence_piece::Type::Normal,
+    "UNKNOWN" => super::model_proto::sentence_piece::Type::Unknown,
+    "CONTROL" => super::model_proto::sentence_piece::Type::Control,
+    "USER_DEFINED" => super::model_proto::sentence_piece::Type::UserDefined,
+    "BYTE" => super::model_proto::sentence_piece::Type::Byte,
+    "UNUSED" => super::model_proto::sentence_piece::Type::Unused,
+);
+
+/// Float value in JSON, either as a number or as the name of a
+/// non-finite value.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FloatValue {
+    Number(f32),
+    Name(String),
+}
+
+/// Serialize non-finite floats by name, since JSON numbers cannot
+/// represent them.
+mod float_name {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::FloatValue;
+
+    pub fn serialize<S>(value: &Option<f32>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(v) if v.is_nan() => serializer.serialize_str("NaN"),
+            Some(v) if *v == f32::INFINITY => serializer.serialize_str("Infinity"),
+            Some(v) if *v == f32::NEG_INFINITY => serializer.serialize_str("-Infinity"),
+            Some(v) => serializer.serialize_f32(*v),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match Option::<FloatValue>::deserialize(deserializer)? {
+            Some(FloatValue::Number(value)) => Ok(Some(value)),
+            Some(FloatValue::Name(name)) => match name.as_str() {
+                "NaN" => Ok(Some(f32::NAN)),
+                "Infinity" => Ok(Some(f32::INFINITY)),
+                "-Infinity" => Ok(Some(f32::NEG_INFINITY)),
+                _ => Err(D::Error::custom(format!("invalid float value: {}", name))),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// Serialize bytes as base64 strings.
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match value {
+            Some(bytes) => serializer.serialize_str(&BASE64.encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|encoded| BASE64.decode(encoded).map_err(D::Error::custom))
+            .transpose()
+    }
+}
+
+impl ModelProto {
+    /// Serialize the model to human-readable JSON.
+    ///
+    /// Enumeration values are stored by their names, non-finite floats as
+    /// `"NaN"`, `"Infinity"` or `"-Infinity"`, and the precompiled
+    /// normalization rules are encoded as base64. Fields that are not set
+    /// in the model are omitted, so that deserialization using
+    /// [`from_json`](Self::from_json) results in the same model.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Cannot serialize model to JSON")
+    }
+
+    /// Deserialize a model from JSON.
+    ///
+    /// The model can be loaded directly using
+    /// [`SentencePieceProcessor::from_json`](crate::SentencePieceProcessor::from_json).
+    pub fn from_json(json: &str) -> Result<Self, SentencePieceError> {
+        serde_json::from_str(json)
+            .map_err(|err| SentencePieceError::InvalidModelJson(err.to_string()))
+    }
+}
@@ -0,0 +1,489 @@
+use std::any::Any;
+use std::collections::HashSet;
+use std::ffi::{c_void, CString};
+use std::os::raw::{c_char, c_uchar};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+
+use num_traits::FromPrimitive;
+use sentencepiece_sys::{spt_train, spt_train_from_iterator};
+
+use crate::{CData, ModelType, SentencePieceError, SentencePieceProcessor};
+
+/// Options for training a sentencepiece model.
+///
+/// The options are validated before training, so that invalid options
+/// are reported before sentencepiece starts reading the corpus. Models can
+/// be trained on files using [`train`](Self::train) or on sentences from
+/// an iterator using [`train_from_iter`](Self::train_from_iter).
+///
+/// ```no_run
+/// use sentencepiece::{ModelType, SentencePieceProcessor, TrainerOptions};
+///
+/// TrainerOptions::new()
+///     .model_type(ModelType::Bpe)
+///     .vocab_size(1000)
+///     .train(&["corpus.txt"], "bpe")
+///     .unwrap();
+/// let spp = SentencePieceProcessor::open("bpe.model").unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainerOptions {
+    model_type: ModelType,
+    vocab_size: u32,
+    character_coverage: f32,
+    user_defined_symbols: Vec<String>,
+    control_symbols: Vec<String>,
+    unk_id: u32,
+    bos_id: Option<u32>,
+    eos_id: Option<u32>,
+    pad_id: Option<u32>,
+    byte_fallback: bool,
+    split_digits: bool,
+    max_sentence_length: usize,
+    num_threads: usize,
+}
+
+impl Default for TrainerOptions {
+    fn default() -> Self {
+        TrainerOptions {
+            model_type: ModelType::Unigram,
+            vocab_size: 8000,
+            character_coverage: 0.9995,
+            user_defined_symbols: Vec::new(),
+            control_symbols: Vec::new(),
+            unk_id: 0,
+            bos_id: Some(1),
+            eos_id: Some(2),
+            pad_id: None,
+            byte_fallback: false,
+            split_digits: false,
+            max_sentence_length: 4192,
+            num_threads: 16,
+        }
+    }
+}
+
+impl TrainerOptions {
+    /// Construct trainer options with the defaults of sentencepiece.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the identifier of the begin of sentence piece, `None` disables
+    /// the piece (default: 1).
+    pub fn bos_id(mut self, bos_id: Option<u32>) -> Self {
+        self.bos_id = bos_id;
+        self
+    }
+
+    /// Decompose unknown characters into UTF-8 byte pieces (default: false).
+    pub fn byte_fallback(mut self, byte_fallback: bool) -> Self {
+        self.byte_fallback = byte_fallback;
+        self
+    }
+
+    /// Set the fraction of characters that is covered by the model, must
+    /// be in *(0, 1]* (default: 0.9995).
+    pub fn character_coverage(mut self, character_coverage: f32) -> Self {
+        self.character_coverage = character_coverage;
+        self
+    }
+
+    /// Set control symbols (default: none).
+    ///
+    /// Control symbols are reserved in the vocabulary, but are never
+    /// produced by segmentation.
+    pub fn control_symbols(mut self, symbols: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.control_symbols = symbols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the identifier of the end of sentence piece, `None` disables the
+    /// piece (default: 2).
+    pub fn eos_id(mut self, eos_id: Option<u32>) -> Self {
+        self.eos_id = eos_id;
+        self
+    }
+
+    /// Set the maximum length of a sentence in bytes, longer sentences are
+    /// skipped (default: 4192).
+    pub fn max_sentence_length(mut self, max_sentence_length: usize) -> Self {
+        self.max_sentence_length = max_sentence_length;
+        self
+    }
+
+    /// Set the type of model to train (default: unigram).
+    pub fn model_type(mut self, model_type: ModelType) -> Self {
+        self.model_type = model_type;
+        self
+    }
+
+    /// Set the number of training threads (default: 16).
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Set the identifier of the padding piece, `None` disables the piece
+    /// (default: `None`).
+    pub fn pad_id(mut self, pad_id: Option<u32>) -> Self {
+        self.pad_id = pad_id;
+        self
+    }
+
+    /// Split numbers into single digits (default: false).
+    pub fn split_digits(mut self, split_digits: bool) -> Self {
+        self.split_digits = split_digits;
+        self
+    }
+
+    /// Set the identifier of the unknown piece (default: 0).
+    pub fn unk_id(mut self, unk_id: u32) -> Self {
+        self.unk_id = unk_id;
+        self
+    }
+
+    /// Set user-defined symbols (default: none).
+    ///
+    /// User-defined symbols are always segmented as a single piece.
+    pub fn user_defined_symbols(
+        mut self,
+        symbols: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.user_defined_symbols = symbols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the vocabulary size (default: 8000).
+    pub fn vocab_size(mut self, vocab_size: u32) -> Self {
+        self.vocab_size = vocab_size;
+        self
+    }
+
+    /// Train a model on the sentences in the given files.
+    ///
+    /// Every line of an input file is a sentence. The model and vocabulary
+    /// are written to `<model_prefix>.model` and `<model_prefix>.vocab`.
+    pub fn train(
+        &self,
+        input: &[impl AsRef<Path>],
+        model_prefix: impl AsRef<Path>,
+    ) -> Result<(), SentencePieceError> {
+        self.validate()?;
+
+        if input.is_empty() {
+            return Err(SentencePieceError::InvalidTrainerOptions(
+                "at least one input file is required".to_string(),
+            ));
+        }
+
+        let input = input
+            .iter()
+            .map(|path| path_to_option(path.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut kwargs = self.to_kwargs();
+        kwargs.push(("input", input.join(",")));
+        kwargs.push(("model_prefix", path_to_option(model_prefix.as_ref())?));
+
+        train_with_kwargs(&kwargs)
+    }
+
+    /// Train a model on the sentences of an iterator.
+    ///
+    /// The model is trained in memory, no files are written.
+    pub fn train_from_iter(
+        &self,
+        sentences: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<SentencePieceProcessor, SentencePieceError> {
+        SentencePieceProcessor::from_serialized_proto(
+            &self.train_from_iter_as_serialized_proto(sentences)?,
+        )
+    }
+
+    /// Train a model on the sentences of an iterator.
+    ///
+    /// The model is trained in memory and returned as a serialized
+    /// protocol buffer, no files are written.
+    pub fn train_from_iter_as_serialized_proto(
+        &self,
+        sentences: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Vec<u8>, SentencePieceError> {
+        self.validate()?;
+        train_from_iter_with_kwargs(&self.to_kwargs(), sentences.into_iter())
+    }
+
+    fn to_kwargs(&self) -> Vec<(&'static str, String)> {
+        let model_type = match self.model_type {
+            ModelType::Unigram => "unigram",
+            ModelType::Bpe => "bpe",
+            ModelType::Word => "word",
+            ModelType::Char => "char",
+        };
+
+        let optional_id = |id: Option<u32>| id.map(|id| id as i64).unwrap_or(-1).to_string();
+
+        vec![
+            ("model_type", model_type.to_string()),
+            ("vocab_size", self.vocab_size.to_string()),
+            ("character_coverage", self.character_coverage.to_string()),
+            ("user_defined_symbols", self.user_defined_symbols.join(",")),
+            ("control_symbols", self.control_symbols.join(",")),
+            ("unk_id", self.unk_id.to_string()),
+            ("bos_id", optional_id(self.bos_id)),
+            ("eos_id", optional_id(self.eos_id)),
+            ("pad_id", optional_id(self.pad_id)),
+            ("byte_fallback", self.byte_fallback.to_string()),
+            ("split_digits", self.split_digits.to_string()),
+            ("max_sentence_length", self.max_sentence_length.to_string()),
+            ("num_threads", self.num_threads.to_string()),
+        ]
+    }
+
+    fn validate(&self) -> Result<(), SentencePieceError> {
+        let invalid = |message: String| Err(SentencePieceError::InvalidTrainerOptions(message));
+
+        if self.vocab_size == 0 {
+            return invalid("vocabulary size must be positive".to_string());
+        }
+
+        if !(self.character_coverage > 0.0 && self.character_coverage <= 1.0) {
+            return invalid(format!(
+                "character coverage must be in (0, 1], was: {}",
+                self.character_coverage
+            ));
+        }
+
+        if self.max_sentence_length == 0 {
+            return invalid("maximum sentence length must be positive".to_string());
+        }
+
+        if self.num_threads == 0 {
+            return invalid("number of threads must be positive".to_string());
+        }
+
+        let mut symbols = HashSet::new();
+        for symbol in self
+            .user_defined_symbols
+            .iter()
+            .chain(self.control_symbols.iter())
+        {
+            // Symbols are passed to sentencepiece as a comma-separated list.
+            if symbol.is_empty() || symbol.contains(',') || symbol.contains('\0') {
+                return invalid(format!(
+                    "symbol must be non-empty and cannot contain commas or nul bytes: {:?}",
+                    symbol
+                ));
+            }
+            if !symbols.insert(symbol) {
+                return invalid(format!("duplicate symbol: {}", symbol));
+            }
+        }
+
+        let special_ids = [
+            ("unk_id", Some(self.unk_id)),
+            ("bos_id", self.bos_id),
+            ("eos_id", self.eos_id),
+            ("pad_id", self.pad_id),
+        ];
+        for (idx, &(name, id)) in special_ids.iter().enumerate() {
+            let id = match id {
+                Some(id) => id,
+                None => continue,
+            };
+
+            if id >= self.vocab_size {
+                return invalid(format!(
+                    "{} must be smaller than the vocabulary size, was: {}",
+                    name, id
+                ));
+            }
+
+            if let Some((other, _)) = special_ids[..idx]
+                .iter()
+                .find(|(_, other_id)| *other_id == Some(id))
+            {
+                return invalid(format!("{} and {} are both: {}", other, name, id));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Convert a path to a trainer option value.
+fn path_to_option(path: &Path) -> Result<String, SentencePieceError> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| SentencePieceError::FilenameNotUtf8(path.to_owned()))?;
+
+    if path_str.contains('\0') {
+        return Err(SentencePieceError::FilenameContainsNul(path.to_owned()));
+    }
+
+    // Input files are passed to sentencepiece as a comma-separated list.
+    if path_str.contains(',') {
+        return Err(SentencePieceError::InvalidTrainerOptions(format!(
+            "filename cannot contain commas: {}",
+            path_str
+        )));
+    }
+
+    Ok(path_str.to_owned())
+}
+
+/// Call `f` with the keys and values of trainer options as C strings.
+fn with_c_kwargs<R>(
+    kwargs: &[(&str, String)],
+    f: impl FnOnce(*const *const c_char, *const *const c_char) -> R,
+) -> R {
+    let c_keys = kwargs
+        .iter()
+        .map(|(key, _)| CString::new(*key).expect("Trainer option contains nul byte"))
+        .collect::<Vec<_>>();
+    let c_values = kwargs
+        .iter()
+        .map(|(_, value)| CString::new(value.as_str()).expect("Trainer option contains nul byte"))
+        .collect::<Vec<_>>();
+    let key_ptrs = c_keys
+        .iter()
+        .map(|key| key.as_ptr())
+        .collect::<Vec<*const c_char>>();
+    let value_ptrs = c_values
+        .iter()
+        .map(|value| value.as_ptr())
+        .collect::<Vec<*const c_char>>();
+
+    f(key_ptrs.as_ptr(), value_ptrs.as_ptr())
+}
+
+/// Convert a status code and message returned by the trainer.
+fn check_train_status(status: i32, message: CData) -> Result<(), SentencePieceError> {
+    if status == 0 {
+        return Ok(());
+    }
+
+    let c_error = match FromPrimitive::from_i32(status) {
+        Some(error) => error,
+        None => unreachable!(),
+    };
+    Err(SentencePieceError::TrainError(
+        c_error,
+        String::from_utf8_lossy(&message).into_owned(),
+    ))
+}
+
+/// Train a model with options in `spm_train` format.
+fn train_with_kwargs(kwargs: &[(&str, String)]) -> Result<(), SentencePieceError> {
+    let mut message = ptr::null_mut();
+    let mut message_len = 0;
+    let status = with_c_kwargs(kwargs, |keys, values| unsafe {
+        spt_train(keys, values, kwargs.len(), &mut message, &mut message_len)
+    });
+
+    check_train_status(
+        status,
+        CData {
+            data: message,
+            len: message_len,
+        },
+    )
+}
+
+/// State of the sentence callback that is passed to the trainer.
+struct SentenceState<I: Iterator> {
+    sentences: I,
+
+    /// The current sentence, the trainer copies it before requesting the
+    /// next sentence.
+    current: Option<I::Item>,
+
+    /// Panic payload when the iterator panicked.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+unsafe extern "C" fn next_sentence<I>(
+    state: *mut c_void,
+    sentence: *mut *const c_uchar,
+    sentence_len: *mut usize,
+) -> bool
+where
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    let state = &mut *(state as *mut SentenceState<I>);
+
+    // Unwinding into C++ is undefined behavior, so end the iteration and
+    // resume unwinding after training.
+    if state.panic.is_some() {
+        return false;
+    }
+
+    match panic::catch_unwind(AssertUnwindSafe(|| state.sentences.next())) {
+        Ok(Some(next)) => {
+            let current = state.current.insert(next);
+            let current: &str = (*current).as_ref();
+            *sentence = current.as_ptr();
+            *sentence_len = current.len();
+            true
+        }
+        Ok(None) => false,
+        Err(payload) => {
+            state.panic = Some(payload);
+            false
+        }
+    }
+}
+
+/// Train a model with options in `spm_train` format on the given sentences.
+fn train_from_iter_with_kwargs<I>(
+    kwargs: &[(&str, String)],
+    sentences: I,
+) -> Result<Vec<u8>, SentencePieceError>
+where
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    let mut state = SentenceState {
+        sentences,
+        current: None,
+        panic: None,
+    };
+
+    let mut model = ptr::null_mut();
+    let mut model_len = 0;
+    let mut message = ptr::null_mut();
+    let mut message_len = 0;
+    let status = with_c_kwargs(kwargs, |keys, values| unsafe {
+        spt_train_from_iterator(
+            keys,
+            values,
+            kwargs.len(),
+            Some(next_sentence::<I>),
+            &mut state as *mut SentenceState<I> as *mut c_void,
+            &mut model,
+            &mut model_len,
+            &mut message,
+            &mut message_len,
+        )
+    });
+
+    let model = CData {
+        data: model,
+        len: model_len,
+    };
+    let message = CData {
+        data: message,
+        len: message_len,
+    };
+
+    if let Some(payload) = state.panic {
+        panic::resume_unwind(payload);
+    }
+
+    check_train_status(status, message)?;
+
+    Ok(model.to_vec())
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::slice;
+use std::vec;
+
+use crate::sentencepiece_model::ModelProto;
+use crate::PieceType;
+
+/// Vocabulary entry of a sentencepiece model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VocabEntry {
+    /// The sentence piece as a string.
+    pub piece: String,
+
+    /// The vocabulary identifier of the sentence piece.
+    pub id: u32,
+
+    /// The score of the sentence piece.
+    pub score: f32,
+
+    /// The type of the sentence piece.
+    pub piece_type: PieceType,
+}
+
+/// Vocabulary of a sentencepiece model.
+///
+/// The vocabulary is a snapshot of the pieces of a model that is stored
+/// in Rust memory, so lookups do not call into the `sentencepiece`
+/// library. A `Vocab` is immutable and can be shared between threads by
+/// wrapping it in an [`Arc`](std::sync::Arc).
+#[derive(Clone, Debug)]
+pub struct Vocab {
+    entries: Vec<VocabEntry>,
+    piece_ids: HashMap<String, u32>,
+    output_piece_score: bool,
+}
+
+impl Vocab {
+    pub(crate) fn from_model_proto(model: ModelProto) -> Self {
+        let output_piece_score = model
+            .trainer_spec
+            .as_ref()
+            .map(|trainer_spec| trainer_spec.vocabulary_output_piece_score())
+            .unwrap_or(true);
+
+        let entries = model
+            .pieces
+            .into_iter()
+            .enumerate()
+            .map(|(id, piece)| VocabEntry {
+                piece: piece.piece.unwrap_or_default(),
+                id: id as u32,
+                score: piece.score.unwrap_or_default(),
+                piece_type: piece
+                    .r#type
+                    .map(|piece_type| {
+                        PieceType::from_proto(piece_type).expect("Model has an unknown piece type")
+                    })
+                    .unwrap_or(PieceType::Normal),
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_entries(entries, output_piece_score)
+    }
+
+    fn from_entries(entries: Vec<VocabEntry>, output_piece_score: bool) -> Self {
+        let piece_ids = entries
+            .iter()
+            .map(|entry| (entry.piece.clone(), entry.id))
+            .collect();
+
+        Vocab {
+            entries,
+            piece_ids,
+            output_piece_score,
+        }
+    }
+
+    /// Get the vocabulary entry with the given identifier.
+    pub fn get(&self, id: u32) -> Option<&VocabEntry> {
+        self.entries.get(id as usize)
+    }
+
+    /// Get the piece with the given identifier.
+    pub fn id_to_piece(&self, id: u32) -> Option<&str> {
+        self.get(id).map(|entry| entry.piece.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over the vocabulary entries in identifier order.
+    pub fn iter(&self) -> slice::Iter<'_, VocabEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Get the identifier of a piece.
+    pub fn piece_to_id(&self, piece: &str) -> Option<u32> {
+        self.piece_ids.get(piece).copied()
+    }
+
+    /// Read a vocabulary in TSV format.
+    ///
+    /// Each line contains a piece, optionally followed by a tab and a
+    /// value. This reads both the `.vocab` files written by the
+    /// sentencepiece trainer, where the value is the piece score, and
+    /// frequency files as used by
+    /// [`SentencePieceProcessor::load_vocabulary`](crate::SentencePieceProcessor::load_vocabulary).
+    /// As in sentencepiece, the value defaults to 1 when it is absent.
+    ///
+    /// Identifiers are assigned in line order. The format does not store
+    /// piece types, so all pieces are read as normal pieces. Scores are
+    /// only written back by [`write_tsv`](Self::write_tsv) when the file
+    /// contains at least one score.
+    ///
+    /// The pieces can be used to restrict the vocabulary of a model:
+    ///
+    /// ```
+    /// use sentencepiece::{SentencePieceProcessor, Vocab};
+    ///
+    /// let mut spp = SentencePieceProcessor::open("testdata/toy.model").unwrap();
+    /// let vocab = Vocab::read_tsv("▁I\t5\n▁saw\t1\n".as_bytes()).unwrap();
+    /// let pieces = vocab
+    ///     .iter()
+    ///     .filter(|entry| entry.score >= 2.)
+    ///     .map(|entry| entry.piece.as_str())
+    ///     .collect::<Vec<_>>();
+    /// spp.set_vocabulary(&pieces).unwrap();
+    /// ```
+    pub fn read_tsv(read: impl BufRead) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        let mut has_scores = false;
+        for line in read.lines() {
+            let line = line?;
+            let mut parts = line.split('\t');
+            let piece = parts.next().unwrap_or_default();
+            if piece.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Vocabulary line without piece: {}", line),
+                ));
+            }
+
+            let score = match parts.next() {
+                Some(value) => {
+                    has_scores = true;
+                    value.parse().map_err(|_| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("Invalid value for piece {}: {}", piece, value),
+                        )
+                    })?
+                }
+                None => 1.,
+            };
+
+            entries.push(VocabEntry {
+                piece: piece.to_owned(),
+                id: entries.len() as u32,
+                score,
+                piece_type: PieceType::Normal,
+            });
+        }
+
+        Ok(Self::from_entries(entries, has_scores))
+    }
+
+    /// Write the vocabulary in TSV format.
+    ///
+    /// The output is the same as the `.vocab` file that the sentencepiece
+    /// trainer writes: each line contains a piece and its score, separated
+    /// by a tab. Scores are omitted when the model was trained with
+    /// `vocabulary_output_piece_score` disabled. Pieces are written
+    /// verbatim, so whitespace is represented by the meta symbol (U+2581)
+    /// that sentencepiece uses in pieces. As in sentencepiece, pieces are
+    /// not escaped, a piece that contains a tab or a newline cannot be read
+    /// back correctly.
+    pub fn write_tsv(&self, mut write: impl Write) -> io::Result<()> {
+        for entry in &self.entries {
+            if self.output_piece_score {
+                writeln!(write, "{}\t{}", entry.piece, format_score(entry.score))?;
+            } else {
+                writeln!(write, "{}", entry.piece)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> IntoIterator for &'a Vocab {
+    type Item = &'a VocabEntry;
+    type IntoIter = slice::Iter<'a, VocabEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl IntoIterator for Vocab {
+    type Item = VocabEntry;
+    type IntoIter = vec::IntoIter<VocabEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// Format a score in the same way as sentencepiece.
+///
+/// sentencepiece formats scores with six significant digits, which is
+/// equivalent to the `%g` format of `printf`.
+fn format_score(score: f32) -> String {
+    const PRECISION: i32 = 6;
+
+    if !score.is_finite() {
+        return if score.is_nan() {
+            "nan".to_string()
+        } else if score > 0. {
+            "inf".to_string()
+        } else {
+            "-inf".to_string()
+        };
+    }
+
+    if score == 0. {
+        return if score.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+
+    // Round to the precision to get the decimal exponent.
+    let scientific = format!("{:.*e}", PRECISION as usize - 1, score);
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("Scientific notation without exponent");
+    let exponent: i32 = exponent.parse().expect("Invalid exponent");
+
+    if (-4..PRECISION).contains(&exponent) {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exponent) as usize, score);
+        strip_trailing_zeros(&fixed).to_string()
+    } else {
+        format!(
+            "{}e{}{:02}",
+            strip_trailing_zeros(mantissa),
+            if exponent < 0 { '-' } else { '+' },
+            exponent.abs()
+        )
+    }
+}
+
+fn strip_trailing_zeros(number: &str) -> &str {
+    if number.contains('.') {
+        number.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        number
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_score;
+
+    #[test]
+    fn formats_scores_like_printf() {
+        assert_eq!(format_score(0.), "0");
+        assert_eq!(format_score(-2.5), "-2.5");
+        assert_eq!(format_score(-3.251134), "-3.25113");
+        assert_eq!(format_score(0.0001), "0.0001");
+        assert_eq!(format_score(0.00001), "1e-05");
+        assert_eq!(format_score(100000.), "100000");
+        assert_eq!(format_score(1000000.), "1e+06");
+        assert_eq!(format_score(123456789.), "1.23457e+08");
+        assert_eq!(format_score(f32::NEG_INFINITY), "-inf");
+    }
+}